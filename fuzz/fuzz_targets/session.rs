@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(
+    init: {
+        // libfuzzer-sys aborts on any panic, including the core faults `try_step` catches; the
+        // panics it doesn't catch still abort, once back in the fuzz target
+        std::panic::set_hook(Box::new(|_| {}));
+    },
+    |data: &[u8]| chip_8_desktop::fuzz::run_bytes(data)
+);
@@ -0,0 +1,134 @@
+use crate::keyboard::{self, KeyValue};
+use ggez::input::keyboard::KeyCode;
+
+/* emulator features bound to host keys, outside of the keypad */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Mute,
+    Reset,
+    PerfOverlay,
+    KeypadOverlay,
+    VolumeUp,
+    VolumeDown,
+    DumpScreen,
+    Zoom,
+    Palette,
+    NextRom,
+}
+
+impl Action {
+    /* `--hotkey` names */
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "mute" => Some(Self::Mute),
+            "reset" => Some(Self::Reset),
+            "perf" => Some(Self::PerfOverlay),
+            "keypad" => Some(Self::KeypadOverlay),
+            "volume-up" => Some(Self::VolumeUp),
+            "volume-down" => Some(Self::VolumeDown),
+            "dump" => Some(Self::DumpScreen),
+            "zoom" => Some(Self::Zoom),
+            "palette" => Some(Self::Palette),
+            "next" => Some(Self::NextRom),
+            _ => None,
+        }
+    }
+
+    /* the others would be toggled back and forth while the key is held */
+    fn repeats(self) -> bool {
+        matches!(self, Self::VolumeUp | Self::VolumeDown)
+    }
+}
+
+/* host key names accepted by `--hotkey`, lowercase */
+#[rustfmt::skip]
+const KEY_NAMES: [(&str, KeyCode); 46] = [
+    ("a", KeyCode::A), ("b", KeyCode::B), ("c", KeyCode::C), ("d", KeyCode::D),
+    ("e", KeyCode::E), ("f", KeyCode::F), ("g", KeyCode::G), ("h", KeyCode::H),
+    ("i", KeyCode::I), ("j", KeyCode::J), ("k", KeyCode::K), ("l", KeyCode::L),
+    ("m", KeyCode::M), ("n", KeyCode::N), ("o", KeyCode::O), ("p", KeyCode::P),
+    ("q", KeyCode::Q), ("r", KeyCode::R), ("s", KeyCode::S), ("t", KeyCode::T),
+    ("u", KeyCode::U), ("v", KeyCode::V), ("w", KeyCode::W), ("x", KeyCode::X),
+    ("y", KeyCode::Y), ("z", KeyCode::Z),
+    ("f1", KeyCode::F1), ("f2", KeyCode::F2), ("f3", KeyCode::F3), ("f4", KeyCode::F4),
+    ("f5", KeyCode::F5), ("f6", KeyCode::F6), ("f7", KeyCode::F7), ("f8", KeyCode::F8),
+    ("f9", KeyCode::F9), ("f10", KeyCode::F10), ("f11", KeyCode::F11), ("f12", KeyCode::F12),
+    ("equals", KeyCode::Equals), ("minus", KeyCode::Minus),
+    ("numpad-add", KeyCode::NumpadAdd), ("numpad-subtract", KeyCode::NumpadSubtract),
+    ("space", KeyCode::Space), ("tab", KeyCode::Tab), ("enter", KeyCode::Return),
+    ("backspace", KeyCode::Back),
+];
+
+/* Host key of each action. Shortcuts are matched by key code (the symbol on the key) while the
+ * keypad goes by scancode (the position), so conflicts are checked through the US layout the
+ * keypad mapping is described with.
+ */
+pub struct Shortcuts {
+    bindings: Vec<(Action, &'static str)>, // several keys can trigger the same action
+}
+
+impl Shortcuts {
+    /* `hotkeys` replace the default keys of their actions */
+    pub fn new(hotkeys: &[(Action, &'static str)]) -> Self {
+        let mut bindings = vec![
+            (Action::Mute, "m"),
+            (Action::Reset, "f5"),
+            (Action::PerfOverlay, "f3"),
+            (Action::KeypadOverlay, "f4"),
+            (Action::VolumeUp, "equals"),
+            (Action::VolumeUp, "numpad-add"),
+            (Action::VolumeDown, "minus"),
+            (Action::VolumeDown, "numpad-subtract"),
+            (Action::DumpScreen, "f12"),
+            (Action::Zoom, "f6"),
+            (Action::Palette, "f7"),
+            (Action::NextRom, "f8"),
+        ];
+
+        bindings.retain(|(action, key)| {
+            hotkeys
+                .iter()
+                // a key triggers a single action
+                .all(|(rebound, hotkey)| rebound != action && hotkey != key)
+        });
+        bindings.extend_from_slice(hotkeys);
+
+        Self { bindings }
+    }
+
+    pub fn action(&self, keycode: Option<KeyCode>, repeated: bool) -> Option<Action> {
+        let keycode = keycode?;
+        self.bindings
+            .iter()
+            .find(|(_, name)| key_code(name) == Some(keycode))
+            .map(|(action, _)| *action)
+            .filter(|action| !repeated || action.repeats())
+    }
+
+    /* the first shortcut also pressing a keypad key, with the bindings of the ROM settings */
+    pub fn keypad_conflict(&self, key_bindings: &[(u32, KeyValue)]) -> Option<&'static str> {
+        self.bindings.iter().map(|(_, name)| *name).find(|name| {
+            keyboard::parse_host_key(name)
+                .and_then(|scancode| keyboard::bound_key(key_bindings, scancode))
+                .is_some()
+        })
+    }
+}
+
+fn key_code(name: &str) -> Option<KeyCode> {
+    KEY_NAMES
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|(_, keycode)| *keycode)
+}
+
+/* `--hotkey` value, e.g. "reset=f6" */
+pub fn parse_hotkey(arg: &str) -> Option<(Action, &'static str)> {
+    let (action, key) = arg.split_once('=')?;
+    let key = key.trim().to_lowercase();
+    let name = KEY_NAMES
+        .iter()
+        .map(|(name, _)| *name)
+        .find(|name| *name == key)?;
+    Some((Action::parse(action.trim())?, name))
+}
@@ -0,0 +1,1031 @@
+use crate::actions::{self, Shortcuts};
+use crate::beep::Beep;
+use crate::cartridge::Cartridge;
+use crate::emulator::{DEFAULT_CLOCK_SPEED, HUNG_THRESHOLD, UNCAPPED};
+use crate::error::Error;
+use crate::rom_file::RomFile;
+use crate::rom_settings::RomSettings;
+use crate::screen::{self, Orientation, Renderer, Scaling};
+use crate::variant::{self, Variant};
+use crate::visual_beep::VisualBeep;
+use crate::{demo, headless, keyboard, logging, ProgramOptions};
+use std::path::Path;
+use std::str::FromStr;
+
+/* below this, the emulator would execute less than one instruction per tick */
+const MIN_CLOCK_SPEED: u32 = 50;
+
+/* single command line token; flags can carry their value inline (`--clock=700`) */
+pub enum Arg<'a> {
+    Flag(&'a str, Option<&'a str>),
+    Positional(&'a str),
+}
+
+/* command line arguments, not including the program name */
+pub struct Args<'a> {
+    rest: &'a [String],
+}
+
+impl<'a> Args<'a> {
+    pub fn new(args: &'a [String]) -> Self {
+        Self { rest: args }
+    }
+
+    pub fn next_arg(&mut self) -> Option<Arg<'a>> {
+        let (arg, rest) = self.rest.split_first()?;
+        self.rest = rest;
+
+        if !arg.starts_with('-') || arg == "-" {
+            return Some(Arg::Positional(arg));
+        }
+
+        Some(match arg.split_once('=') {
+            Some((flag, value)) => Arg::Flag(flag, Some(value)),
+            None => Arg::Flag(arg, None),
+        })
+    }
+
+    /* the inline value if present, otherwise the following argument (whatever it looks like) */
+    pub fn value(&mut self, flag: &str, inline: Option<&'a str>) -> Result<&'a str, Error> {
+        if let Some(value) = inline {
+            return Ok(value);
+        }
+
+        let (value, rest) = self
+            .rest
+            .split_first()
+            .ok_or_else(|| Error::MissingValue(flag.to_owned()))?;
+        self.rest = rest;
+
+        Ok(value)
+    }
+}
+
+fn invalid(flag: &str, value: &str, expected: &'static str) -> Error {
+    Error::InvalidValue(flag.to_owned(), value.to_owned(), expected)
+}
+
+/* a set of keypad keys, as hex digits */
+fn parse_key_set(value: &str) -> Option<[bool; 16]> {
+    let mut res = [false; 16];
+    for c in value.chars() {
+        res[c.to_digit(16)? as usize] = true;
+    }
+    Some(res)
+}
+
+fn no_value(flag: &str, inline: Option<&str>) -> Result<(), Error> {
+    match inline {
+        Some(value) => Err(invalid(flag, value, "no value")),
+        None => Ok(()),
+    }
+}
+
+pub fn parse_number<T: FromStr + PartialOrd>(
+    flag: &str,
+    value: &str,
+    range: std::ops::RangeInclusive<T>,
+    expected: &'static str,
+) -> Result<T, Error> {
+    value
+        .parse::<T>()
+        .ok()
+        .filter(|x| range.contains(x))
+        .ok_or_else(|| invalid(flag, value, expected))
+}
+
+/* `args` includes the program name, as returned by `std::env::args()` */
+pub fn parse(args: &[String]) -> Result<ProgramOptions, Error> {
+    let args = match args.split_first() {
+        Some((_, rest)) => rest,
+        None => return Err(Error::InvalidArguments),
+    };
+
+    let defaults = ProgramOptions {
+        clock_speed: DEFAULT_CLOCK_SPEED,
+        volume: 100,
+        beep: Beep::default(),
+        audio: cfg!(feature = "audio"),
+        vsync: true,
+        watchdog: Some(HUNG_THRESHOLD),
+        ..Default::default()
+    };
+
+    // launched without arguments, e.g. from a shortcut: something to look at rather than an error
+    if args.is_empty() {
+        return Ok(ProgramOptions {
+            rom_name: demo::NAME.to_owned(),
+            program: demo::PROGRAM.to_vec(),
+            ..defaults
+        });
+    }
+
+    // the settings shipped with the ROM sit between the defaults and the flags, so the flags are
+    // applied twice: first to find the ROM, then on top of its settings
+    let (first_pass, rom_path, _) = apply_flags(args, defaults.clone())?;
+
+    // a spectator only shows what the host runs
+    if let Some(address) = &first_pass.mirror {
+        if let Some(path) = rom_path {
+            return Err(Error::UnexpectedArgument(path.to_owned()));
+        }
+        return Ok(ProgramOptions {
+            rom_name: format!("mirror of {}", address),
+            ..first_pass
+        });
+    }
+
+    if let Some(file) = &first_pass.playlist_file {
+        if let Some(path) = rom_path {
+            return Err(Error::UnexpectedArgument(path.to_owned()));
+        }
+        return load_playlist(args, file);
+    }
+
+    let rom_path = rom_path.ok_or(Error::InvalidArguments)?;
+    let rom = RomFile::load(Path::new(rom_path), first_pass.rom_entry.as_deref())?;
+    rom.check_size(Path::new(rom_path))?;
+    let cartridge = match &rom.sidecar {
+        Some((name, text)) => Some(Cartridge::parse(&Path::new(rom_path).join(name), text)?),
+        None => Cartridge::load(Path::new(rom_path))?,
+    };
+
+    let mut base = defaults;
+    if let Some(cartridge) = &cartridge {
+        cartridge.apply(&mut base);
+    }
+    // the user's own settings for the ROM override the ones recommended by its author
+    if let Some(settings) = RomSettings::load(Path::new(rom_path))? {
+        settings.apply(&mut base);
+    }
+    let (mut options, _, verify) = apply_flags(args, base)?;
+
+    options.rom_name = rom.stem;
+    if let Some(Cartridge {
+        title: Some(title),
+        authors,
+        ..
+    }) = &cartridge
+    {
+        options.rom_name = if authors.is_empty() {
+            title.clone()
+        } else {
+            format!("{} by {}", title, authors.join(", "))
+        };
+    }
+    options.program = rom.program;
+
+    // a benchmark measures how fast the emulator can go
+    if options.bench.is_some() {
+        options.clock_speed = UNCAPPED;
+    }
+
+    // `--verify` is an alias of `--lockstep` with the quirks the ROM runs with (detected ones
+    // included), so that any divergence comes from the frontend's callbacks and stepping
+    if verify {
+        variant::resolve(&mut options);
+        options.lockstep = Some((options.clip_sprites, options.schip_compatibility));
+    }
+
+    // a shortcut on a keypad key would do both at once
+    let shortcuts = Shortcuts::new(&options.hotkeys);
+    if let Some(key) = shortcuts.keypad_conflict(&options.key_bindings) {
+        return Err(Error::InvalidValue(
+            "--hotkey".to_owned(),
+            key.to_owned(),
+            "a key outside of the keypad",
+        ));
+    }
+
+    // spectators can only press keys of a windowed host
+    if options.remote_keys.contains(&true) && options.mirror_host.is_none() {
+        return Err(Error::MissingValue("--mirror-host".to_owned()));
+    }
+
+    if options.shader.is_some() && options.renderer == Renderer::Software {
+        return Err(Error::InvalidValue(
+            "--renderer".to_owned(),
+            "software".to_owned(),
+            "auto or gpu, shaders need the GPU renderer",
+        ));
+    }
+
+    // the cycle a reference image was taken at
+    if options.compare.is_some() && options.max_cycles == 0 {
+        return Err(Error::MissingValue("--at-cycle".to_owned()));
+    }
+
+    // a headless run has no window to close, so it must be bounded (a benchmark by its duration)
+    let lockstep = options.lockstep.is_some();
+    let headless = options.headless || options.quirk_ab || lockstep;
+    let headless_bench = options.bench.is_some() && !options.quirk_ab && !lockstep;
+    if headless && !headless_bench && options.max_cycles == 0 {
+        return Err(Error::MissingValue("--max-cycles".to_owned()));
+    }
+
+    Ok(options)
+}
+
+/* `--playlist`: each line is the command line of an entry, its ROM (relative to the playlist)
+ * followed by flags applied on top of the other ones; lines starting with '#' are ignored */
+fn load_playlist(args: &[String], file: &str) -> Result<ProgramOptions, Error> {
+    let invalid = |reason: String| Error::Playlist(file.to_owned(), reason);
+    let text = std::fs::read_to_string(file).map_err(|e| invalid(e.to_string()))?;
+    let dir = Path::new(file).parent().unwrap_or_else(|| Path::new(""));
+
+    // the flags shared by the entries, without the playlist itself
+    let mut shared = vec![String::new()]; // in place of the program name
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        if arg == "--playlist" {
+            rest.next();
+        } else if !arg.starts_with("--playlist=") {
+            shared.push(arg.clone());
+        }
+    }
+
+    let mut entries = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let mut tokens = line.split_whitespace();
+        let rom = match tokens.next() {
+            Some(rom) if !rom.starts_with('#') => dir.join(rom),
+            _ => continue,
+        };
+        let entry: Vec<String> = shared
+            .iter()
+            .cloned()
+            .chain(tokens.map(str::to_owned))
+            .chain(std::iter::once(rom.to_string_lossy().into_owned()))
+            .collect();
+        let mut options = parse(&entry).map_err(|e| invalid(format!("line {}: {}", i + 1, e)))?;
+        // each entry runs on the machine its own ROM needs
+        variant::resolve(&mut options);
+        entries.push(options);
+    }
+
+    let mut res = entries
+        .first()
+        .cloned()
+        .ok_or_else(|| invalid("no ROM listed".to_owned()))?;
+    res.playlist = entries;
+    Ok(res)
+}
+
+/* for flags setting the same thing, such as `--at-cycle` and `--max-cycles`: only one of them can
+ * be given */
+fn one_of<'a>(given: &mut Option<&'a str>, flag: &'a str) -> Result<(), Error> {
+    match given.replace(flag) {
+        Some(other) if other != flag => {
+            Err(Error::ConflictingFlags(other.to_owned(), flag.to_owned()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/* returns `options` updated by the flags, the ROM path if given, and whether `--verify` was */
+fn apply_flags(
+    args: &[String],
+    mut options: ProgramOptions,
+) -> Result<(ProgramOptions, Option<&str>, bool), Error> {
+    let mut args = Args::new(args);
+    let mut rom_path = None;
+
+    let mut cycle_flag = None; // `--at-cycle` or `--max-cycles`, if given
+    let mut lockstep_flag = None; // `--lockstep` or `--verify`, if given
+
+    while let Some(arg) = args.next_arg() {
+        let (flag, inline) = match arg {
+            Arg::Positional(path) => {
+                // the ROM is the only argument not requiring a flag
+                if rom_path.replace(path).is_some() {
+                    return Err(Error::UnexpectedArgument(path.to_owned()));
+                }
+                continue;
+            }
+            Arg::Flag(flag, inline) => (flag, inline),
+        };
+
+        match flag {
+            "--clip-sprites" | "-K" => {
+                no_value(flag, inline)?;
+                options.clip_sprites = true;
+            }
+            "--schip-opcodes" | "-S" => {
+                no_value(flag, inline)?;
+                options.variant = Some(Variant::Schip);
+            }
+            "--variant" => {
+                let value = args.value(flag, inline)?;
+                options.variant = match value {
+                    "auto" => None,
+                    _ => Some(
+                        Variant::parse(value)
+                            .ok_or_else(|| invalid(flag, value, "auto, chip8 or schip"))?,
+                    ),
+                };
+            }
+            "--playlist" => {
+                options.playlist_file = Some(args.value(flag, inline)?.to_owned());
+            }
+            "--advance-after" => {
+                let value = args.value(flag, inline)?;
+                let secs = parse_number(flag, value, 1..=u64::MAX, "a positive number of seconds")?;
+                options.advance_after = Some(std::time::Duration::from_secs(secs));
+            }
+            "--entry" => {
+                options.rom_entry = Some(args.value(flag, inline)?.to_owned());
+            }
+            "--headless" => {
+                no_value(flag, inline)?;
+                options.headless = true;
+            }
+            "--hotkey" => {
+                let value = args.value(flag, inline)?;
+                let hotkey = actions::parse_hotkey(value)
+                    .ok_or_else(|| invalid(flag, value, "ACTION=KEY, e.g. reset=f6"))?;
+                options.hotkeys.push(hotkey);
+            }
+            "--key-diagnostics" => {
+                no_value(flag, inline)?;
+                options.key_diagnostics = true;
+            }
+            "--tui" => {
+                no_value(flag, inline)?;
+                options.tui = true;
+            }
+            "--quirk-ab" => {
+                no_value(flag, inline)?;
+                options.quirk_ab = true;
+            }
+            "--screen-off-pause" => {
+                no_value(flag, inline)?;
+                options.pause_when_screen_off = true;
+            }
+            "--run-in-background" => {
+                no_value(flag, inline)?;
+                options.run_in_background = true;
+            }
+            "--background-throttle" => {
+                let value = args.value(flag, inline)?;
+                let percent = parse_number(flag, value, 1..=99, "a percentage between 1 and 99")?;
+                options.background_throttle = Some(percent);
+            }
+            "--display-wait" => {
+                no_value(flag, inline)?;
+                options.display_wait = true;
+            }
+            "--mouse-bind" | "-M" => {
+                let value = args.value(flag, inline)?;
+                let binding = keyboard::parse_mouse_binding(value)
+                    .ok_or_else(|| invalid(flag, value, "[input]=[key]"))?;
+                options.mouse_bindings.push(binding);
+            }
+            "--wait-release" => {
+                no_value(flag, inline)?;
+                options.wait_for_release = true;
+            }
+            "--touch-keypad" => {
+                no_value(flag, inline)?;
+                options.touch_keypad = true;
+            }
+            "--direct-input" => {
+                no_value(flag, inline)?;
+                options.direct_input = true;
+            }
+            "--turbo" => {
+                let value = args.value(flag, inline)?;
+                let expected = "[key]=[rate], with a rate between 1 and 30 Hz";
+                let (key, rate) = value
+                    .split_once('=')
+                    .ok_or_else(|| invalid(flag, value, expected))?;
+                let key = u8::from_str_radix(key, 16)
+                    .ok()
+                    .filter(|key| *key <= 0xF)
+                    .ok_or_else(|| invalid(flag, value, expected))?;
+                let rate = rate
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|rate| (1..=keyboard::MAX_TURBO_RATE).contains(rate))
+                    .ok_or_else(|| invalid(flag, value, expected))?;
+                options.turbo.push((key, rate));
+            }
+            "--volume" => {
+                let value = args.value(flag, inline)?;
+                options.volume = parse_number(flag, value, 0..=100, "a volume between 0 and 100")?;
+            }
+            "--beep" => {
+                let value = args.value(flag, inline)?;
+                options.beep =
+                    Beep::parse(value).ok_or_else(|| invalid(flag, value, "[waveform]@[hz]"))?;
+            }
+            // only skips the sound source: ggez still opens the audio device with the window, unless
+            // built without the `audio` feature
+            "--no-audio" => {
+                no_value(flag, inline)?;
+                options.audio = false;
+            }
+            "--visual-beep" => {
+                let value = args.value(flag, inline)?;
+                options.visual_beep = Some(
+                    VisualBeep::parse(value)
+                        .ok_or_else(|| invalid(flag, value, "border or icon"))?,
+                );
+            }
+            "--rumble" => {
+                let value = args.value(flag, inline)?;
+                options.rumble =
+                    parse_number(flag, value, 0..=100, "an intensity between 0 and 100")?;
+            }
+            "--screen-off" => {
+                let value = args.value(flag, inline)?;
+                let secs = parse_number(flag, value, 1..=u64::MAX, "a positive number of seconds")?;
+                options.screen_off_after = Some(std::time::Duration::from_secs(secs));
+            }
+            "--watchdog" => {
+                let value = args.value(flag, inline)?;
+                let secs = parse_number(flag, value, 0..=u64::MAX, "a number of seconds")?;
+                options.watchdog = match secs {
+                    0 => None,
+                    secs => Some(std::time::Duration::from_secs(secs)),
+                };
+            }
+            "--lockstep" => {
+                let value = args.value(flag, inline)?;
+                let quirks = headless::parse_quirks(value)
+                    .ok_or_else(|| invalid(flag, value, "K, S, KS or none"))?;
+                options.lockstep = Some(quirks);
+                one_of(&mut lockstep_flag, flag)?;
+            }
+            "--fb-out" => {
+                options.fb_out = Some(args.value(flag, inline)?.to_owned());
+            }
+            "--compare" => {
+                options.compare = Some(args.value(flag, inline)?.to_owned());
+            }
+            "--at-cycle" => {
+                let value = args.value(flag, inline)?;
+                options.max_cycles =
+                    parse_number(flag, value, 1..=u64::MAX, "a number of instructions")?;
+                one_of(&mut cycle_flag, flag)?;
+            }
+            // set up by `parse`, once the quirks are known. Both runs are the same ROM with the same
+            // quirks and seed: this only checks that the frontend is deterministic, there is no
+            // second backend to compare the core against
+            "--verify" => {
+                no_value(flag, inline)?;
+                one_of(&mut lockstep_flag, flag)?;
+            }
+            "--max-cycles" => {
+                let value = args.value(flag, inline)?;
+                options.max_cycles =
+                    parse_number(flag, value, 0..=u64::MAX, "a number of instructions")?;
+                one_of(&mut cycle_flag, flag)?;
+            }
+            "--bench" => {
+                let value = args.value(flag, inline)?;
+                let secs = parse_number(flag, value, 1..=u64::MAX, "a positive number of seconds")?;
+                options.bench = Some(std::time::Duration::from_secs(secs));
+            }
+            "--log-level" => {
+                let value = args.value(flag, inline)?;
+                let level = logging::parse_level(value).ok_or_else(|| {
+                    invalid(flag, value, "off, error, warn, info, debug or trace")
+                })?;
+                options.log_level = Some(level);
+            }
+            "--log-file" => {
+                let value = args.value(flag, inline)?;
+                options.log_file = Some(value.to_owned());
+            }
+            "--mirror-host" => {
+                let value = args.value(flag, inline)?;
+                options.mirror_host = Some(value.to_owned());
+            }
+            "--mirror" => {
+                let value = args.value(flag, inline)?;
+                options.mirror = Some(value.to_owned());
+            }
+            "--disable-input" => {
+                let value = args.value(flag, inline)?;
+                if !keyboard::INPUT_SOURCES.contains(&value) {
+                    return Err(invalid(flag, value, "keyboard, mouse, network or chat"));
+                }
+                options.disabled_inputs.push(value.to_owned());
+            }
+            "--twitch-plays" => {
+                let value = args.value(flag, inline)?;
+                options.twitch_plays = Some(value.to_owned());
+            }
+            "--remote-keys" => {
+                let value = args.value(flag, inline)?;
+                options.remote_keys = parse_key_set(value)
+                    .ok_or_else(|| invalid(flag, value, "keypad keys, e.g. CD"))?;
+            }
+            "--summary" => {
+                let value = args.value(flag, inline)?;
+                options.summary = Some(value.to_owned());
+            }
+            "--stats-out" => {
+                let value = args.value(flag, inline)?;
+                options.stats_out = Some(value.to_owned());
+            }
+            "--scale" => {
+                let value = args.value(flag, inline)?;
+                let scale = parse_number(flag, value, 1..=64, "a scale factor between 1 and 64")?;
+                options.scale = Some(scale);
+            }
+            "--scaling" => {
+                let value = args.value(flag, inline)?;
+                options.scaling = Scaling::parse(value)
+                    .ok_or_else(|| invalid(flag, value, "integer or smooth"))?;
+            }
+            "--rotate" => {
+                let value = args.value(flag, inline)?;
+                options.orientation.quarter_turns = Orientation::parse_rotation(value)
+                    .ok_or_else(|| invalid(flag, value, "0, 90, 180 or 270"))?;
+            }
+            "--flip" => {
+                let value = args.value(flag, inline)?;
+                (options.orientation.mirror, options.orientation.flip) = match value {
+                    "none" => (false, false),
+                    "horizontal" => (true, false),
+                    "vertical" => (false, true),
+                    "both" => (true, true),
+                    _ => return Err(invalid(flag, value, "none, horizontal, vertical or both")),
+                };
+            }
+            "--gpu-backend" => {
+                let value = args.value(flag, inline)?;
+                options.gpu_backend = screen::parse_backend(value)
+                    .ok_or_else(|| invalid(flag, value, "auto, vulkan, dx12, dx11, metal or gl"))?;
+            }
+            "--palette" => {
+                let value = args.value(flag, inline)?;
+                let colors = screen::parse_palette(value)
+                    .ok_or_else(|| invalid(flag, value, "comma-separated #RRGGBB colors"))?;
+                options.palette.set(&colors);
+            }
+            "--border" => {
+                let value = args.value(flag, inline)?;
+                options.border = screen::parse_color(value)
+                    .ok_or_else(|| invalid(flag, value, "a #RRGGBB color"))?;
+            }
+            "--background" => {
+                options.background = Some(args.value(flag, inline)?.to_owned());
+            }
+            "--vsync" => {
+                let value = args.value(flag, inline)?;
+                // ggez only tells FIFO presentation from immediate, there is no adaptive mode
+                options.vsync = match value {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(invalid(flag, value, "on or off")),
+                };
+            }
+            "--shader" => {
+                options.shader = Some(args.value(flag, inline)?.to_owned());
+            }
+            "--watch-shader" => {
+                options.shader = Some(args.value(flag, inline)?.to_owned());
+                options.watch_shader = true;
+            }
+            "--renderer" => {
+                let value = args.value(flag, inline)?;
+                options.renderer = Renderer::parse(value)
+                    .ok_or_else(|| invalid(flag, value, "auto, gpu or software"))?;
+            }
+            "--clock" | "-C" => {
+                const EXPECTED: &str = "a clock speed of at least 50 Hz, or 0 for uncapped";
+
+                let value = args.value(flag, inline)?;
+                let clock_speed = parse_number(flag, value, UNCAPPED..=u32::MAX, EXPECTED)?;
+                if clock_speed != UNCAPPED && clock_speed < MIN_CLOCK_SPEED {
+                    return Err(invalid(flag, value, EXPECTED));
+                }
+                options.clock_speed = clock_speed;
+            }
+            _ => return Err(Error::UnknownFlag(flag.to_owned())),
+        }
+    }
+
+    Ok((options, rom_path, lockstep_flag == Some("--verify")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* a temporary directory of the test's own, removed along with its content when dropped */
+    struct TempDir(std::path::PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            // the process id keeps concurrent test runs apart
+            let path =
+                std::env::temp_dir().join(format!("chip-8-args-{}-{}", std::process::id(), name));
+            std::fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /* path of a one-instruction ROM, deleted along with its directory when dropped */
+    struct TempRom {
+        path: String,
+        _dir: TempDir,
+    }
+
+    impl std::ops::Deref for TempRom {
+        type Target = str;
+
+        fn deref(&self) -> &str {
+            &self.path
+        }
+    }
+
+    fn rom(name: &str) -> TempRom {
+        let dir = TempDir::new(name);
+        let path = dir.0.join(format!("{}.ch8", name));
+        std::fs::write(&path, [0x12, 0x00]).unwrap();
+        TempRom {
+            path: path.to_string_lossy().into_owned(),
+            _dir: dir,
+        }
+    }
+
+    fn parse_args(args: &[&str]) -> Result<ProgramOptions, Error> {
+        let args: Vec<String> = std::iter::once("chip-8-desktop")
+            .chain(args.iter().copied())
+            .map(String::from)
+            .collect();
+        parse(&args)
+    }
+
+    #[test]
+    fn clock_consumes_its_value() {
+        let rom = rom("clock-separate");
+        let options = parse_args(&["--clock", "700", &rom]).unwrap();
+        assert_eq!(options.clock_speed, 700);
+        assert_eq!(options.program, [0x12, 0x00]);
+
+        // the value must not be mistaken for the ROM, whatever the order
+        let options = parse_args(&[&rom, "-C", "700"]).unwrap();
+        assert_eq!(options.clock_speed, 700);
+    }
+
+    #[test]
+    fn clock_inline_value() {
+        let rom = rom("clock-inline");
+        let options = parse_args(&["--clock=700", &rom]).unwrap();
+        assert_eq!(options.clock_speed, 700);
+
+        let options = parse_args(&["-C=1000", &rom]).unwrap();
+        assert_eq!(options.clock_speed, 1000);
+    }
+
+    #[test]
+    fn clock_default() {
+        let rom = rom("clock-default");
+        let options = parse_args(&[&rom]).unwrap();
+        assert_eq!(options.clock_speed, DEFAULT_CLOCK_SPEED);
+    }
+
+    #[test]
+    fn clock_out_of_range() {
+        let rom = rom("clock-range");
+        for value in ["1", "49", "4294967296", "-5", "fast"] {
+            assert!(matches!(
+                parse_args(&["--clock", value, &rom]),
+                Err(Error::InvalidValue(..))
+            ));
+        }
+    }
+
+    #[test]
+    fn clock_uncapped_and_above_u16() {
+        let rom = rom("clock-wide");
+        let options = parse_args(&["--clock", "0", &rom]).unwrap();
+        assert_eq!(options.clock_speed, UNCAPPED);
+
+        let options = parse_args(&["--clock=500000", &rom]).unwrap();
+        assert_eq!(options.clock_speed, 500_000);
+    }
+
+    #[test]
+    fn clock_missing_value() {
+        let rom = rom("clock-missing");
+        assert!(matches!(
+            parse_args(&[&rom, "--clock"]),
+            Err(Error::MissingValue(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_flags_and_extra_roms() {
+        let rom = rom("extra");
+        assert!(matches!(
+            parse_args(&["--warp-speed", &rom]),
+            Err(Error::UnknownFlag(_))
+        ));
+        assert!(matches!(
+            parse_args(&[&rom, &rom]),
+            Err(Error::UnexpectedArgument(_))
+        ));
+        assert!(matches!(
+            parse_args(&["-K=yes", &rom]),
+            Err(Error::InvalidValue(..))
+        ));
+    }
+
+    #[test]
+    fn turbo_rate_is_bounded() {
+        let rom = rom("turbo");
+        let options = parse_args(&["--turbo", "a=30", &rom]).unwrap();
+        assert_eq!(options.turbo, [(0xA, 30)]);
+
+        for value in ["a=31", "a=0", "g=10", "a"] {
+            assert!(matches!(
+                parse_args(&["--turbo", value, &rom]),
+                Err(Error::InvalidValue(..))
+            ));
+        }
+    }
+
+    #[test]
+    fn palette_replaces_the_first_entries() {
+        let rom = rom("palette");
+        let options = parse_args(&["--palette", "#102030, #FFFFFF", &rom]).unwrap();
+        assert_eq!(options.palette.0[0], [0x10, 0x20, 0x30]);
+        assert_eq!(options.palette.0[2], screen::Palette::default().0[2]);
+
+        let options = parse_args(&["--palette", "high-contrast", &rom]).unwrap();
+        assert_eq!(options.palette.0[..2], [[0x00; 3], [0xFF; 3]]);
+
+        for value in ["102030", "#1020", "#10203g", ""] {
+            assert!(matches!(
+                parse_args(&["--palette", value, &rom]),
+                Err(Error::InvalidValue(..))
+            ));
+        }
+    }
+
+    #[test]
+    fn hotkeys_stay_off_the_keypad() {
+        let rom = rom("hotkey");
+        let options = parse_args(&["--hotkey", "reset=F6", &rom]).unwrap();
+        assert_eq!(options.hotkeys, [(actions::Action::Reset, "f6")]);
+
+        for value in ["reset=q", "mute=5", "rewind=f6", "reset=f13"] {
+            assert!(matches!(
+                parse_args(&["--hotkey", value, &rom]),
+                Err(Error::InvalidValue(..))
+            ));
+        }
+    }
+
+    #[test]
+    fn requires_a_rom() {
+        assert!(matches!(
+            parse_args(&["-C", "700"]),
+            Err(Error::InvalidArguments)
+        ));
+
+        // unless there are no arguments at all
+        let options = parse_args(&[]).unwrap();
+        assert_eq!(options.program, demo::PROGRAM);
+    }
+
+    #[test]
+    fn mirror_runs_without_a_rom() {
+        let options = parse_args(&["--mirror", "localhost:7000"]).unwrap();
+        assert_eq!(options.mirror.as_deref(), Some("localhost:7000"));
+        assert!(options.program.is_empty());
+
+        let rom = rom("mirror");
+        assert!(matches!(
+            parse_args(&["--mirror", "localhost:7000", &rom]),
+            Err(Error::UnexpectedArgument(_))
+        ));
+    }
+
+    #[test]
+    fn remote_keys_require_a_host() {
+        let rom = rom("remote-keys");
+        assert!(matches!(
+            parse_args(&["--remote-keys", "CD", &rom]),
+            Err(Error::MissingValue(_))
+        ));
+        assert!(matches!(
+            parse_args(&["--mirror-host", ":7000", "--remote-keys", "CG", &rom]),
+            Err(Error::InvalidValue(..))
+        ));
+
+        let options = parse_args(&["--mirror-host", ":7000", "--remote-keys", "cD", &rom]).unwrap();
+        let owned: Vec<usize> = (0..16).filter(|k| options.remote_keys[*k]).collect();
+        assert_eq!(owned, [0xC, 0xD]);
+    }
+
+    #[test]
+    fn headless_requires_max_cycles() {
+        let rom = rom("headless");
+        assert!(matches!(
+            parse_args(&["--headless", &rom]),
+            Err(Error::MissingValue(_))
+        ));
+
+        let options = parse_args(&["--headless", "--max-cycles=100", &rom]).unwrap();
+        assert!(options.headless);
+        assert_eq!(options.max_cycles, 100);
+    }
+
+    #[test]
+    fn compare_requires_a_cycle() {
+        let rom = rom("compare");
+        assert!(matches!(
+            parse_args(&["--compare", "golden.png", &rom]),
+            Err(Error::MissingValue(flag)) if flag == "--at-cycle"
+        ));
+
+        let options = parse_args(&["--compare", "golden.png", "--at-cycle=500", &rom]).unwrap();
+        assert_eq!(options.compare.as_deref(), Some("golden.png"));
+        assert_eq!(options.max_cycles, 500);
+
+        assert!(matches!(
+            parse_args(&[
+                "--at-cycle",
+                "500",
+                "--headless",
+                "--max-cycles",
+                "100",
+                &rom
+            ]),
+            Err(Error::ConflictingFlags(..))
+        ));
+    }
+
+    #[test]
+    fn verify_is_lockstep_with_the_same_quirks() {
+        let rom = rom("verify");
+        let options = parse_args(&["--verify", "--max-cycles=100", &rom]).unwrap();
+        assert_eq!(
+            options.lockstep,
+            Some((options.clip_sprites, options.schip_compatibility))
+        );
+
+        // the value of another flag
+        let options = parse_args(&[
+            "--log-file",
+            "--verify",
+            "--headless",
+            "--max-cycles=100",
+            &rom,
+        ])
+        .unwrap();
+        assert_eq!(options.lockstep, None);
+
+        assert!(matches!(
+            parse_args(&["--verify", "--lockstep", "K", "--max-cycles=100", &rom]),
+            Err(Error::ConflictingFlags(..))
+        ));
+    }
+
+    #[test]
+    fn sidecar_settings_below_flags() {
+        let rom = rom("sidecar");
+        let sidecar = std::path::Path::new(&*rom).with_extension("json");
+        std::fs::write(
+            &sidecar,
+            r#"{ "title": "Demo", "authors": ["A", "B"],
+                 "options": { "tickrate": 20, "clipQuirks": true, "shiftQuirks": false } }"#,
+        )
+        .unwrap();
+
+        let options = parse_args(&[&rom]).unwrap();
+        assert_eq!(options.clock_speed, 1200);
+        assert!(options.clip_sprites);
+        assert!(options.variant == Some(Variant::Chip8));
+        assert_eq!(options.rom_name, "Demo by A, B");
+
+        let options = parse_args(&["-C", "700", "-S", &rom]).unwrap();
+        assert_eq!(options.clock_speed, 700);
+        assert!(options.variant == Some(Variant::Schip));
+
+        std::fs::write(&sidecar, r#"{ "tickrate": "fast" }"#).unwrap();
+        assert!(matches!(
+            parse_args(&[&rom]),
+            Err(Error::InvalidSidecar(..))
+        ));
+    }
+
+    #[test]
+    fn rom_settings_below_flags() {
+        let rom = rom("rom-settings");
+        let settings = std::path::Path::new(&*rom).with_extension("toml");
+        std::fs::write(&settings, "clock = 900\n[keys]\n5 = \"space\"\n").unwrap();
+
+        let options = parse_args(&[&rom]).unwrap();
+        assert_eq!(options.clock_speed, 900);
+        assert_eq!(options.key_bindings, [(0x39, 0x5)]);
+
+        let options = parse_args(&["--clock=700", &rom]).unwrap();
+        assert_eq!(options.clock_speed, 700);
+
+        std::fs::write(&settings, "turbo = true\n").unwrap();
+        assert!(matches!(
+            parse_args(&[&rom]),
+            Err(Error::InvalidSidecar(..))
+        ));
+    }
+
+    #[test]
+    fn rom_from_a_zip_archive() {
+        use std::io::Write;
+
+        let dir = TempDir::new("zip");
+        let path = dir.0.join("pack.zip");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+        let stored =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        for (name, program) in [
+            ("games/pong.ch8", [0x12, 0x00]),
+            ("tetris.c8", [0x12, 0x02]),
+        ] {
+            zip.start_file(name, stored).unwrap();
+            zip.write_all(&program).unwrap();
+        }
+        zip.start_file("games/pong.json", stored).unwrap();
+        zip.write_all(br#"{ "title": "Pong" }"#).unwrap();
+        zip.finish().unwrap();
+        let path = path.to_string_lossy().into_owned();
+
+        assert!(matches!(
+            parse_args(&[&path]),
+            Err(Error::InvalidArchive(_, reason)) if reason.ends_with("games/pong.ch8, tetris.c8")
+        ));
+
+        let options = parse_args(&["--entry", "pong.ch8", &path]).unwrap();
+        assert_eq!(options.program, [0x12, 0x00]);
+        assert_eq!(options.rom_name, "Pong");
+
+        let options = parse_args(&["--entry", "tetris.c8", &path]).unwrap();
+        assert_eq!(options.program, [0x12, 0x02]);
+        assert_eq!(options.rom_name, "tetris");
+    }
+
+    #[test]
+    fn playlist_entries_override_the_shared_flags() {
+        let temp = TempDir::new("playlist");
+        let dir = &temp.0;
+        std::fs::write(dir.join("a.ch8"), [0x12, 0x00]).unwrap();
+        // 00FF switches SCHIP to high resolution
+        std::fs::write(dir.join("b.ch8"), [0x00, 0xFF, 0x12, 0x02]).unwrap();
+        let list = dir.join("party.txt");
+        std::fs::write(&list, "# demos\na.ch8\n\nb.ch8 -C 900 --advance-after 5\n").unwrap();
+        let list = list.to_string_lossy().into_owned();
+
+        let options = parse_args(&["-C", "700", "--playlist", &list]).unwrap();
+        assert_eq!(options.program, [0x12, 0x00]);
+        let entries: Vec<_> = options
+            .playlist
+            .iter()
+            .map(|entry| {
+                (
+                    entry.rom_name.as_str(),
+                    entry.clock_speed,
+                    entry.advance_after,
+                    entry.schip_compatibility,
+                )
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("a", 700, None, false),
+                ("b", 900, Some(std::time::Duration::from_secs(5)), true)
+            ]
+        );
+
+        assert!(matches!(
+            parse_args(&["--playlist", &list, &rom("extra")]),
+            Err(Error::UnexpectedArgument(_))
+        ));
+    }
+
+    #[test]
+    fn bench_is_uncapped_and_bounded() {
+        let rom = rom("bench");
+        let options = parse_args(&["--headless", "--bench", "3", "-C", "700", &rom]).unwrap();
+        assert_eq!(options.bench, Some(std::time::Duration::from_secs(3)));
+        assert_eq!(options.clock_speed, UNCAPPED);
+
+        assert!(matches!(
+            parse_args(&["--quirk-ab", "--bench=3", &rom]),
+            Err(Error::MissingValue(_))
+        ));
+    }
+}
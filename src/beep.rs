@@ -0,0 +1,243 @@
+/* buzzer synthesis: the sound timer gates a generated tone instead of a bundled sample */
+// nothing is synthesized without the audio feature
+#![cfg_attr(not(feature = "audio"), allow(dead_code))]
+
+use crate::error::Error;
+#[cfg(feature = "audio")]
+use std::sync::atomic::{AtomicBool, Ordering::Relaxed};
+#[cfg(feature = "audio")]
+use std::sync::Arc;
+
+const SAMPLE_RATE: u32 = 44_100;
+const AMPLITUDE: f32 = 0.25 * i16::MAX as f32;
+
+/* Fade in and out of the tone. Cutting a wave mid-period clicks, which games firing short beeps in
+ * rapid succession turn into a rattle; a few milliseconds are too short to be heard as a fade.
+ */
+const RAMP_SAMPLES: u32 = SAMPLE_RATE / 200; // 5 ms
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+}
+
+/* Tone streamed to the output device for the whole session, gated rather than paused: pausing the
+ * stream would stop it dead, without a chance to fade out. */
+#[cfg(feature = "audio")]
+struct Sound {
+    sink: rodio::Sink,
+    gate: Arc<AtomicBool>, // read by `Tone` on the audio thread
+}
+
+#[cfg(feature = "audio")]
+impl Sound {
+    fn set_volume(&mut self, volume: f32) {
+        self.sink.set_volume(volume);
+    }
+
+    fn pause(&self) {
+        self.gate.store(false, Relaxed);
+    }
+
+    fn resume(&self) {
+        self.gate.store(true, Relaxed);
+    }
+}
+
+#[cfg(feature = "audio")]
+struct Tone {
+    beep: Beep,
+    t: u32,     // samples into the current second, a whole number of periods
+    level: u32, // of the ramp, 0 to RAMP_SAMPLES
+    gate: Arc<AtomicBool>,
+}
+
+#[cfg(feature = "audio")]
+impl Iterator for Tone {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        self.level = if self.gate.load(Relaxed) {
+            (self.level + 1).min(RAMP_SAMPLES)
+        } else {
+            self.level.saturating_sub(1)
+        };
+
+        let sample = self.beep.sample(self.t) as f32 * self.level as f32 / RAMP_SAMPLES as f32;
+        self.t = (self.t + 1) % SAMPLE_RATE;
+
+        Some(sample as i16)
+    }
+}
+
+#[cfg(feature = "audio")]
+impl rodio::Source for Tone {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<std::time::Duration> {
+        None
+    }
+}
+
+/* without the audio feature there is never a sound to play */
+#[cfg(not(feature = "audio"))]
+enum Sound {}
+
+#[cfg(not(feature = "audio"))]
+impl Sound {
+    fn set_volume(&mut self, _: f32) {
+        match *self {}
+    }
+
+    fn pause(&self) {
+        match *self {}
+    }
+
+    fn resume(&self) {
+        match *self {}
+    }
+}
+
+/* The tone, started and stopped by the sound timer. Silent with `--no-audio` (or without the
+ * audio feature), in which case playback is still tracked for the visual beep and the rumble.
+ */
+pub struct Buzzer {
+    sound: Option<Sound>,
+    playing: bool,
+}
+
+#[derive(Clone, Copy)]
+pub struct Beep {
+    pub waveform: Waveform,
+    pub frequency: u16, // Hz
+}
+
+impl Default for Beep {
+    fn default() -> Self {
+        Self {
+            waveform: Waveform::Square,
+            frequency: 440,
+        }
+    }
+}
+
+impl Beep {
+    /* parse a `--beep` value, e.g. "square@440", or just "sine" for the default frequency */
+    pub fn parse(arg: &str) -> Option<Self> {
+        let (waveform, frequency) = match arg.split_once('@') {
+            Some((waveform, frequency)) => (waveform, Some(frequency)),
+            None => (arg, None),
+        };
+
+        let waveform = match waveform {
+            "square" => Waveform::Square,
+            "sine" => Waveform::Sine,
+            "triangle" => Waveform::Triangle,
+            _ => return None,
+        };
+
+        let frequency = match frequency {
+            Some(frequency) => frequency.parse::<u16>().ok()?,
+            None => Beep::default().frequency,
+        };
+
+        // audible range
+        if !(20..=20_000).contains(&frequency) {
+            return None;
+        }
+
+        Some(Self {
+            waveform,
+            frequency,
+        })
+    }
+
+    fn sample(self, t: u32) -> i16 {
+        use std::f32::consts::PI;
+
+        // position within the current period, in [0, 1)
+        let phase =
+            (t as u64 * self.frequency as u64 % SAMPLE_RATE as u64) as f32 / SAMPLE_RATE as f32;
+
+        let value = match self.waveform {
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Sine => (2.0 * PI * phase).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+        };
+
+        (value * AMPLITUDE) as i16
+    }
+}
+
+impl Buzzer {
+    pub fn new(ctx: &ggez::Context, beep: Beep, audio: bool) -> Result<Self, Error> {
+        Ok(Self {
+            sound: if audio { load(ctx, beep)? } else { None },
+            playing: false,
+        })
+    }
+
+    /* in percent, 0 mutes it */
+    pub fn set_volume(&mut self, volume: u8) {
+        if let Some(sound) = &mut self.sound {
+            sound.set_volume(volume.min(100) as f32 / 100.0);
+        }
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+        if let Some(sound) = &self.sound {
+            sound.pause();
+        }
+    }
+
+    pub fn resume(&mut self) {
+        self.playing = true;
+        if let Some(sound) = &self.sound {
+            sound.resume();
+        }
+    }
+
+    /* even if muted or silent */
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+}
+
+/* on the device ggez opened, silent until resumed */
+#[cfg(feature = "audio")]
+fn load(ctx: &ggez::Context, beep: Beep) -> Result<Option<Sound>, Error> {
+    let sink = rodio::Sink::try_new(ctx.audio.device()).map_err(|e| Error::Audio(e.to_string()))?;
+    let gate = Arc::new(AtomicBool::new(false));
+    sink.append(Tone {
+        beep,
+        t: 0,
+        level: 0,
+        gate: Arc::clone(&gate),
+    });
+
+    Ok(Some(Sound { sink, gate }))
+}
+
+#[cfg(not(feature = "audio"))]
+fn load(_: &ggez::Context, _: Beep) -> Result<Option<Sound>, Error> {
+    Ok(None)
+}
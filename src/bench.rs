@@ -0,0 +1,136 @@
+use crate::headless::{self, EXIT_FAULT, EXIT_FINISHED, EXIT_WAITING_FOR_KEY};
+use crate::ProgramOptions;
+use chip_8_desktop::session::Fault;
+use std::time::{Duration, Instant};
+
+/* instructions executed between two looks at the wall clock, in headless mode */
+const CLOCK_CHECK_INTERVAL: u64 = 10_000;
+
+/* results of a `--bench` run, printed as `key: value` lines like the session summary */
+pub struct Report {
+    pub elapsed: Duration,
+    pub instructions: u64,
+    pub draws: u64,            // issued by the ROM
+    pub frames: Option<u64>,   // presented by the window, None when headless
+    pub overhead: Option<f64>, // share of the emulator thread's time spent outside of batches
+    pub waiting_for_key: bool, // the run stopped early (headless) or stalled on input
+    pub fault: Option<Fault>,  // the run stopped early on a core error (headless)
+}
+
+impl Report {
+    pub fn to_text(&self) -> String {
+        let secs = self.elapsed.as_secs_f64();
+        let per_second = |n: u64| if secs > 0.0 { n as f64 / secs } else { 0.0 };
+
+        let mut res = format!(
+            "duration: {:.3}\ninstructions: {}\ninstructions per second: {:.0}\ndraws: {}\n",
+            secs,
+            self.instructions,
+            per_second(self.instructions),
+            self.draws
+        );
+        if let Some(frames) = self.frames {
+            res.push_str(&format!(
+                "frames: {}\nframes per second: {:.1}\n",
+                frames,
+                per_second(frames)
+            ));
+        }
+        if let Some(overhead) = self.overhead {
+            res.push_str(&format!("scheduler overhead: {:.2}%\n", overhead * 100.0));
+        }
+        if let Some(fault) = &self.fault {
+            res.push_str(&format!("halted: {}\n", fault));
+        } else if self.waiting_for_key {
+            res.push_str("halted: waiting for key\n");
+        }
+
+        res
+    }
+}
+
+/* windowed benchmark, the emulator accumulates the statistics and quits once it's over */
+pub struct Bench {
+    started: Instant,
+    duration: Duration,
+    instructions: u64,
+}
+
+impl Bench {
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            started: Instant::now(),
+            duration,
+            instructions: 0,
+        }
+    }
+
+    pub fn count_instructions(&mut self, n: u64) {
+        self.instructions += n;
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.started.elapsed() >= self.duration
+    }
+
+    /* `busy` is the time the emulator thread spent executing instructions */
+    pub fn report(&self, draws: u64, frames: u64, busy: Duration, waiting: bool) -> Report {
+        let elapsed = self.started.elapsed();
+        let overhead = if elapsed > Duration::ZERO {
+            1.0 - (busy.as_secs_f64() / elapsed.as_secs_f64()).min(1.0)
+        } else {
+            0.0
+        };
+
+        Report {
+            elapsed,
+            instructions: self.instructions,
+            draws,
+            frames: Some(frames),
+            overhead: Some(overhead),
+            waiting_for_key: waiting,
+            fault: None,
+        }
+    }
+}
+
+/* `--headless --bench` entry point, returns the process exit status */
+pub fn run_headless(options: &ProgramOptions, duration: Duration) -> i32 {
+    let report = headless::with_stepper(options, |stepper| {
+        let started = Instant::now();
+        let mut fault = None;
+
+        'run: while started.elapsed() < duration {
+            for _ in 0..CLOCK_CHECK_INTERVAL {
+                // there is no input source, the ROM can't make any further progress
+                if stepper.is_waiting_for_key() {
+                    break 'run;
+                }
+                if let Err(e) = stepper.try_step() {
+                    fault = Some(e);
+                    break 'run;
+                }
+            }
+        }
+
+        Report {
+            elapsed: started.elapsed(),
+            instructions: stepper.cycles(),
+            draws: stepper.draws(),
+            frames: None,
+            overhead: None,
+            waiting_for_key: stepper.is_waiting_for_key(),
+            fault,
+        }
+    });
+
+    print!("{}", report.to_text());
+
+    if report.fault.is_some() {
+        EXIT_FAULT
+    } else if report.waiting_for_key {
+        EXIT_WAITING_FOR_KEY
+    } else {
+        EXIT_FINISHED
+    }
+}
@@ -0,0 +1,139 @@
+use crate::error::Error;
+use crate::screen;
+use crate::variant::Variant;
+use crate::ProgramOptions;
+use serde_json::Value;
+use std::path::Path;
+
+/* Settings shipped alongside a ROM, in a `.json` file with the same name. Either a bare Octo
+ * options object, or a CHIP-8 Archive entry wrapping one together with title and authors:
+ *   { "title": "...", "authors": ["..."], "options": { "tickrate": 20, "clipQuirks": true, ... } }
+ * Quirks not supported by the core are ignored.
+ */
+#[derive(Default)]
+pub struct Cartridge {
+    pub title: Option<String>,
+    pub authors: Vec<String>,
+    clock_speed: Option<u32>,
+    clip_sprites: Option<bool>,
+    variant: Option<Variant>,
+    display_wait: Option<bool>,
+    colors: [Option<[u8; 3]>; 4], // palette entries
+}
+
+fn invalid(path: &Path, reason: impl Into<String>) -> Error {
+    Error::InvalidSidecar(path.to_string_lossy().into_owned(), reason.into())
+}
+
+impl Cartridge {
+    /* `None` if the ROM has no sidecar */
+    pub fn load(rom_path: &Path) -> Result<Option<Self>, Error> {
+        let path = rom_path.with_extension("json");
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(invalid(&path, e.to_string())),
+        };
+
+        Self::parse(&path, &text).map(Some)
+    }
+
+    /* `path` only names the settings in errors, they may come from within an archive */
+    pub fn parse(path: &Path, text: &str) -> Result<Self, Error> {
+        let json: Value = serde_json::from_str(text).map_err(|e| invalid(path, e.to_string()))?;
+        Self::from_json(&json).map_err(|reason| invalid(path, reason))
+    }
+
+    fn from_json(json: &Value) -> Result<Self, String> {
+        let mut res = Self::default();
+
+        let json = json.as_object().ok_or("expected an object")?;
+        let options = match json.get("options") {
+            Some(options) => options.as_object().ok_or("\"options\" must be an object")?,
+            None => json,
+        };
+
+        if let Some(title) = json.get("title") {
+            res.title = Some(
+                title
+                    .as_str()
+                    .ok_or("\"title\" must be a string")?
+                    .to_owned(),
+            );
+        }
+        if let Some(authors) = json.get("authors") {
+            res.authors = authors
+                .as_array()
+                .and_then(|authors| {
+                    authors
+                        .iter()
+                        .map(|a| a.as_str().map(String::from))
+                        .collect()
+                })
+                .ok_or("\"authors\" must be a list of strings")?;
+        }
+
+        let flag = |key: &str| match options.get(key) {
+            Some(value) => value
+                .as_bool()
+                .map(Some)
+                .ok_or_else(|| format!("\"{}\" must be a boolean", key)),
+            None => Ok(None),
+        };
+
+        // instructions per 60 Hz frame
+        if let Some(tickrate) = options.get("tickrate") {
+            let tickrate = tickrate
+                .as_u64()
+                .filter(|x| (1..=u32::MAX as u64 / 60).contains(x))
+                .ok_or("\"tickrate\" must be a positive number of instructions")?;
+            res.clock_speed = Some(tickrate as u32 * 60);
+        }
+        res.clip_sprites = flag("clipQuirks")?;
+        res.display_wait = flag("vBlankQuirks")?;
+        // the SCHIP behaviour of the core covers both
+        res.variant = match (flag("shiftQuirks")?, flag("loadStoreQuirks")?) {
+            (None, None) => None,
+            (shift, load_store) if shift == Some(true) || load_store == Some(true) => {
+                Some(Variant::Schip)
+            }
+            _ => Some(Variant::Chip8),
+        };
+
+        // in palette order
+        const COLORS: [&str; 4] = ["backgroundColor", "fillColor", "fillColor2", "blendColor"];
+        for (color, key) in res.colors.iter_mut().zip(COLORS) {
+            if let Some(value) = options.get(key) {
+                *color = Some(
+                    value
+                        .as_str()
+                        .and_then(screen::parse_color)
+                        .ok_or_else(|| format!("\"{}\" must be a \"#RRGGBB\" color", key))?,
+                );
+            }
+        }
+
+        Ok(res)
+    }
+
+    /* to be applied before the command line flags, which take precedence */
+    pub fn apply(&self, options: &mut ProgramOptions) {
+        if let Some(clock_speed) = self.clock_speed {
+            options.clock_speed = clock_speed;
+        }
+        if let Some(clip_sprites) = self.clip_sprites {
+            options.clip_sprites = clip_sprites;
+        }
+        if let Some(variant) = self.variant {
+            options.variant = Some(variant);
+        }
+        if let Some(display_wait) = self.display_wait {
+            options.display_wait = display_wait;
+        }
+        for (entry, color) in options.palette.0.iter_mut().zip(self.colors) {
+            if let Some(color) = color {
+                *entry = color;
+            }
+        }
+    }
+}
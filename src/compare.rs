@@ -0,0 +1,125 @@
+use crate::error::Error;
+use crate::headless::{self, EXIT_DIVERGED, EXIT_FINISHED};
+use crate::screen::is_pixel_on;
+use crate::ProgramOptions;
+use chip_8_core::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use image::{Rgb, RgbImage};
+
+/* size of an emulated pixel in the diff image */
+const DIFF_SCALE: u32 = 8;
+
+const MISSING: Rgb<u8> = Rgb([0xE0, 0x30, 0x30]); // on in the reference only
+const EXTRA: Rgb<u8> = Rgb([0x30, 0xC0, 0x30]); // on in the emulated screen only
+
+/* Reference screen, one entry per emulated pixel. The image may be scaled up by any integer
+ * factor (a screenshot of the window at `--scale N`, without border), and each pixel is told on
+ * or off by whichever of the two palette colors is closest to the center of its square.
+ */
+fn load_reference(path: &str, palette: &[[u8; 3]; 2]) -> Result<Vec<bool>, Error> {
+    let invalid = |reason: String| Error::Reference(path.to_owned(), reason);
+
+    let image = image::open(path)
+        .map_err(|e| invalid(e.to_string()))?
+        .to_rgb8();
+    let scale = image.width() / SCREEN_WIDTH as u32;
+    if scale == 0
+        || image.width() != SCREEN_WIDTH as u32 * scale
+        || image.height() != SCREEN_HEIGHT as u32 * scale
+    {
+        return Err(invalid(format!(
+            "the image is {}x{}, expected {}x{} or a multiple",
+            image.width(),
+            image.height(),
+            SCREEN_WIDTH,
+            SCREEN_HEIGHT
+        )));
+    }
+
+    let distance = |a: &Rgb<u8>, b: &[u8; 3]| -> u32 {
+        a.0.iter()
+            .zip(b)
+            .map(|(&a, &b)| (a as i32 - b as i32).pow(2) as u32)
+            .sum()
+    };
+
+    Ok((0..SCREEN_WIDTH * SCREEN_HEIGHT)
+        .map(|i| {
+            let (x, y) = ((i % SCREEN_WIDTH) as u32, (i / SCREEN_WIDTH) as u32);
+            let color = image.get_pixel(x * scale + scale / 2, y * scale + scale / 2);
+            distance(color, &palette[1]) < distance(color, &palette[0])
+        })
+        .collect())
+}
+
+/* "golden.png" -> "golden.diff.png" */
+fn diff_path(reference: &str) -> String {
+    let path = std::path::Path::new(reference);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}.diff.png", stem))
+        .to_string_lossy()
+        .into_owned()
+}
+
+/* `--compare` entry point: run the ROM headlessly up to `--at-cycle`, and compare the screen with
+ * a reference image; on a mismatch, an image of the differences is written next to it. Returns
+ * the process exit status. */
+pub fn run(options: &ProgramOptions, reference: &str) -> Result<i32, Error> {
+    // bitmaps (e.g. the dumps of `F12`) are black on white, whatever the palette
+    let palette = if reference.ends_with(".pbm") {
+        [[0xFF; 3], [0x00; 3]]
+    } else {
+        [options.palette.0[0], options.palette.0[1]]
+    };
+    let expected = load_reference(reference, &palette)?;
+    let outcome = headless::execute(options);
+
+    if outcome.cycles < options.max_cycles {
+        println!(
+            "note: the ROM stopped at cycle {} ({})",
+            outcome.cycles,
+            if outcome.faulted {
+                "core error"
+            } else {
+                "waiting for key"
+            }
+        );
+    }
+
+    let actual = |x: u32, y: u32| is_pixel_on(&outcome.fb, x as usize, y as usize);
+    let differences = (0..SCREEN_WIDTH * SCREEN_HEIGHT)
+        .filter(|&i| {
+            let (x, y) = ((i % SCREEN_WIDTH) as u32, (i / SCREEN_WIDTH) as u32);
+            actual(x, y) != expected[i]
+        })
+        .count();
+
+    if differences == 0 {
+        println!("match at cycle {}", outcome.cycles);
+        return Ok(EXIT_FINISHED);
+    }
+
+    let diff = RgbImage::from_fn(
+        SCREEN_WIDTH as u32 * DIFF_SCALE,
+        SCREEN_HEIGHT as u32 * DIFF_SCALE,
+        |x, y| {
+            let (x, y) = (x / DIFF_SCALE, y / DIFF_SCALE);
+            match (
+                expected[y as usize * SCREEN_WIDTH + x as usize],
+                actual(x, y),
+            ) {
+                (true, false) => MISSING,
+                (false, true) => EXTRA,
+                (_, on) => Rgb(palette[on as usize]),
+            }
+        },
+    );
+    let path = diff_path(reference);
+    diff.save(&path)
+        .map_err(|e| Error::Reference(path.clone(), e.to_string()))?;
+
+    println!(
+        "{} pixels differ at cycle {}, see {} (red: missing, green: extra)",
+        differences, outcome.cycles, path
+    );
+    Ok(EXIT_DIVERGED)
+}
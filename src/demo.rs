@@ -0,0 +1,43 @@
+/* Run when the emulator is launched without arguments (e.g. from a desktop shortcut), so that
+ * there's something on screen: the "C8" font glyphs and a box bouncing around them, paced by the
+ * delay timer. Written for this project, and in the public domain like any trivial program.
+ */
+pub const NAME: &str = "demo";
+
+#[rustfmt::skip]
+pub const PROGRAM: [u8; 70] = [
+    0x00, 0xE0, // 200  clear
+    0x65, 0x0C, // 202  v5 := 0xC
+    0xF5, 0x29, // 204  i := hex v5
+    0x66, 0x1A, // 206  v6 := 26
+    0x67, 0x0D, // 208  v7 := 13
+    0xD6, 0x75, // 20A  sprite v6 v7 5
+    0x65, 0x08, // 20C  v5 := 8
+    0xF5, 0x29, // 20E  i := hex v5
+    0x66, 0x20, // 210  v6 := 32
+    0xD6, 0x75, // 212  sprite v6 v7 5
+    0xA2, 0x42, // 214  i := box
+    0x60, 0x00, // 216  v0 := 0           position
+    0x61, 0x00, // 218  v1 := 0
+    0x62, 0x01, // 21A  v2 := 1           direction
+    0x63, 0x01, // 21C  v3 := 1
+    0xD0, 0x14, // 21E  loop: sprite v0 v1 4
+    0x64, 0x02, // 220  v4 := 2           30 moves per second
+    0xF4, 0x15, // 222  delay := v4
+    0xF4, 0x07, // 224  wait: v4 := delay
+    0x34, 0x00, // 226  if v4 != 0 then
+    0x12, 0x24, // 228  jump wait
+    0xD0, 0x14, // 22A  sprite v0 v1 4   erase
+    0x80, 0x24, // 22C  v0 += v2
+    0x81, 0x34, // 22E  v1 += v3
+    0x40, 0x3C, // 230  if v0 == 60 then
+    0x62, 0xFF, // 232  v2 := -1
+    0x40, 0x00, // 234  if v0 == 0 then
+    0x62, 0x01, // 236  v2 := 1
+    0x41, 0x1C, // 238  if v1 == 28 then
+    0x63, 0xFF, // 23A  v3 := -1
+    0x41, 0x00, // 23C  if v1 == 0 then
+    0x63, 0x01, // 23E  v3 := 1
+    0x12, 0x1E, // 240  jump loop
+    0xF0, 0x90, 0x90, 0xF0, // 242  box: 4x4 outline
+];
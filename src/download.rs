@@ -0,0 +1,160 @@
+use crate::error::Error;
+use std::path::PathBuf;
+
+/* ROMs are tiny, this leaves room for zipped packs */
+#[cfg(feature = "net")]
+const MAX_DOWNLOAD_SIZE: u64 = 1 << 20;
+
+/* `run <url> [--sha256 <hex>] [flags...]` entry point: fetches the ROM into the cache directory,
+ * returning the arguments to run it with, the URL replaced by the downloaded file. A pinned hash is
+ * checked on every run, and spares the download when the cached copy matches it.
+ */
+pub fn resolve(args: &[String]) -> Result<Vec<String>, Error> {
+    let url = args.get(2).ok_or(Error::InvalidArguments)?;
+    if !url.starts_with("https://") {
+        return Err(Error::InvalidValue(
+            "run".to_owned(),
+            url.clone(),
+            "an https:// URL",
+        ));
+    }
+
+    let mut flags = Vec::new();
+    let mut sha256 = None;
+    let mut rest = args[3..].iter();
+    while let Some(arg) = rest.next() {
+        if arg == "--sha256" {
+            let value = rest
+                .next()
+                .ok_or_else(|| Error::MissingValue("--sha256".to_owned()))?;
+            sha256 = Some(value.to_lowercase());
+        } else if let Some(value) = arg.strip_prefix("--sha256=") {
+            sha256 = Some(value.to_lowercase());
+        } else {
+            flags.push(arg.clone());
+        }
+    }
+
+    let path = fetch(url, sha256.as_deref())?;
+
+    Ok(std::iter::once(args[0].clone())
+        .chain(flags)
+        .chain(std::iter::once(path.to_string_lossy().into_owned()))
+        .collect())
+}
+
+/* the platform's per-user cache, where downloads can be deleted at any time */
+#[cfg(feature = "net")]
+fn cache_dir() -> Option<PathBuf> {
+    let env = |name| std::env::var_os(name).map(PathBuf::from);
+
+    let base = if cfg!(windows) {
+        env("LOCALAPPDATA")
+    } else if cfg!(target_os = "macos") {
+        env("HOME").map(|home| home.join("Library").join("Caches"))
+    } else {
+        env("XDG_CACHE_HOME").or_else(|| env("HOME").map(|home| home.join(".cache")))
+    };
+
+    Some(base?.join("chip-8-desktop").join("roms"))
+}
+
+/* the last segment of the URL's path, kept only if it's a plain file name: on Windows `\`, `..` or
+ * a drive prefix would let the server pick where the download is written
+ */
+#[cfg(any(feature = "net", test))]
+fn file_name(url: &str) -> &str {
+    url.split(['?', '#'])
+        .next()
+        .and_then(|url| url.rsplit(['/', '\\']).next())
+        .filter(|name| {
+            !name.is_empty()
+                && !name.chars().all(|c| c == '.')
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+        })
+        .unwrap_or("rom.ch8")
+}
+
+/* cached as `<hash of the URL>/<file name>`, so that the ROM keeps its name */
+#[cfg(feature = "net")]
+fn fetch(url: &str, sha256: Option<&str>) -> Result<PathBuf, Error> {
+    use sha2::{Digest, Sha256};
+    use std::io::Read;
+
+    let failed = |reason: String| Error::Download(url.to_owned(), reason);
+    let digest = |data: &[u8]| format!("{:x}", Sha256::digest(data));
+
+    let name = file_name(url);
+    let dir = cache_dir()
+        .ok_or_else(|| failed("no cache directory".to_owned()))?
+        .join(format!("{:016x}", crate::headless::fnv1a(url.as_bytes())));
+    let path = dir.join(name);
+
+    if let (Some(expected), Ok(cached)) = (sha256, std::fs::read(&path)) {
+        if digest(&cached) == expected {
+            tracing::info!(path = %path.display(), "using the cached download");
+            return Ok(path);
+        }
+    }
+
+    tracing::info!(url, "downloading");
+    let response = ureq::get(url).call().map_err(|e| failed(e.to_string()))?;
+    let mut data = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_DOWNLOAD_SIZE + 1)
+        .read_to_end(&mut data)
+        .map_err(|e| failed(e.to_string()))?;
+    if data.len() as u64 > MAX_DOWNLOAD_SIZE {
+        return Err(failed(format!(
+            "larger than {} KiB",
+            MAX_DOWNLOAD_SIZE / 1024
+        )));
+    }
+
+    let actual = digest(&data);
+    match sha256 {
+        Some(expected) if actual != expected => {
+            return Err(failed(format!(
+                "SHA-256 is {}, expected {}",
+                actual, expected
+            )))
+        }
+        Some(_) => {}
+        // for pinning it on the next run
+        None => tracing::info!(sha256 = %actual, "downloaded"),
+    }
+
+    std::fs::create_dir_all(&dir)
+        .and_then(|_| std::fs::write(&path, &data))
+        .map_err(|e| failed(format!("cannot write {}: {}", path.display(), e)))?;
+
+    Ok(path)
+}
+
+#[cfg(not(feature = "net"))]
+fn fetch(url: &str, _: Option<&str>) -> Result<PathBuf, Error> {
+    Err(Error::Download(
+        url.to_owned(),
+        "built without the net feature".to_owned(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::file_name;
+
+    #[test]
+    fn file_name_stays_in_the_cache() {
+        assert_eq!(file_name("https://host/roms/pong.ch8?raw=1"), "pong.ch8");
+        assert_eq!(
+            file_name("https://host/x\\..\\..\\Startup\\evil.bat"),
+            "evil.bat"
+        );
+        assert_eq!(file_name("https://host/roms/.."), "rom.ch8");
+        assert_eq!(file_name("https://host/roms/"), "rom.ch8");
+        assert_eq!(file_name("https://host/C:evil.bat"), "rom.ch8");
+    }
+}
@@ -1,33 +1,111 @@
+use crate::actions::{Action, Shortcuts};
+use crate::beep::Buzzer;
+use crate::bench::Bench;
+use crate::error::{Error, ErrorSlot};
 use crate::keyboard::*;
+use crate::keypad::KeypadOverlay;
+use crate::mirror::MirrorHost;
+use crate::osd::Osd;
+use crate::perf::PerfOverlay;
+use crate::persist::PlaySession;
+use crate::rumble::Rumble;
 use crate::screen::*;
+use crate::summary::{IoStats, Summary};
 use crate::timers::*;
+use crate::twitch::TwitchPlays;
+use crate::visual_beep::VisualBeep;
+use crate::zoom::Zoom;
 use crate::ProgramOptions;
 use chip_8_core::FrameBuffer;
 use chip_8_core::{Chip8, IOCallbacks};
-use ggez::audio::SoundSource;
 use ggez::input::keyboard;
-use std::pin::Pin;
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8};
 use std::sync::mpsc;
 use std::sync::mpsc::{Receiver, Sender};
 use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
-pub const DEFAULT_CLOCK_SPEED: u16 = 500;
+pub const DEFAULT_CLOCK_SPEED: u32 = 500;
+
+/* clock speed value meaning "as fast as possible" */
+pub const UNCAPPED: u32 = 0;
+
+/* default `--watchdog` period, without progress before suggesting a reset */
+pub const HUNG_THRESHOLD: Duration = Duration::from_secs(10);
+
+/* 60 Hz display interrupt of the COSMAC VIP, used by the display wait mode */
+const FRAME_NS: u128 = 16_666_667;
+
+pub fn window_title(rom_name: &str, paused: bool) -> String {
+    format!(
+        "Chip-8 Emulator — {}{}",
+        rom_name,
+        if paused { " [paused]" } else { "" }
+    )
+}
 
 pub struct Emulator {
-    internals: Pin<Arc<EmulatorInternals>>,
-    sleeper: spin_sleep::SpinSleeper,
-    keyboard_status: [bool; 16],
-    update_sync_pair: Arc<(Condvar, Mutex<State>)>,
-    esp: EmulationSpeedParams,
+    internals: Arc<EmulatorInternals>,
+    inputs: Inputs,
+    keyboard_input: Sender<KeyMessage>, // host events, polled by `inputs` like any other source
+    mouse_input: Sender<KeyMessage>,
+    mouse_bindings: Vec<(MouseInput, KeyValue)>,
+    key_bindings: Vec<(u32, KeyValue)>, // scancode, keypad key
+    wheel_taps: Vec<KeyValue>,
+    osd: Osd,
+    perf: PerfOverlay,
+    keypad: KeypadOverlay,
+    zoom: Zoom,
+    palette: usize,                // 0: the one configured, then `screen::PRESETS`
+    touched_key: Option<KeyValue>, // held through the on-screen keypad
+    volume: u8,
+    muted: bool,
+    visual_beep: Option<VisualBeep>,
+    beeping: bool, // the visual beep is on screen
+    rumble: Option<Rumble>,
+    last_fb: FrameBuffer,
+    last_activity: u64, // calls made by the core, see `IoStats::total`
+    stale_since: Instant,
+    stale_instructions: u64, // instruction count at `stale_since`
+    hung_hint_shown: bool,   // until the ROM makes progress again, even if dismissed
+    last_input: Instant,
+    screen_off_after: Option<Duration>,
+    pause_when_screen_off: bool,
+    pause_in_background: bool,
+    background_throttle: Option<u8>,
+    throttled: bool, // the emulated clock is slowed down by `background_throttle`
+    focused: bool,
+    minimized: bool,
+    ghosting_warned: bool,
+    shortcuts: Shortcuts,
+    host_key: Option<(u32, KeyAction)>, // last host key event, for `--key-diagnostics`
+    frame_dirty: bool, // the last frame shows something other than the emulated screen
+    shader_error_shown: bool, // `--shader`, until the shader builds
+    rom_name: String,
+    title_paused: bool, // state shown in the window title
+    summary: Summary,
+    plays: PlaySession,
+    summary_target: Option<String>, // None once written
+    stats: Arc<IoStats>,            // kept across restarts, unlike the machine
+    stats_target: Option<String>,   // None once written
+    bench: Option<Bench>,
+    mirror: Option<MirrorHost>,
+    options: ProgramOptions, // to rebuild the machine after a crash
+    playlist: Vec<ProgramOptions>,
+    playlist_position: usize,
+    entry_started: Instant,
+    crashed: bool, // a worker thread panicked, waiting for a restart or quit
 }
 
-/* state machine to handle waiting on a keypress */
+/* emulator thread control, set by the main thread */
 #[derive(Clone, Copy, Default, PartialEq, Eq)]
 enum State {
     #[default]
-    Ready,
-    UpdateRequested,
-    WaitingForKey,
+    Running,
+    Paused,
+    ShuttingDown, // final, the emulator thread exits as soon as it sees it
 }
 
 struct EmulationSpeedParams {
@@ -37,7 +115,17 @@ struct EmulationSpeedParams {
 }
 
 impl EmulationSpeedParams {
-    fn new(clock_speed: u16) -> Self {
+    fn new(clock_speed: u32) -> Self {
+        // no time budget: ticks run back to back, each as large as a capped one at ~500 kHz
+        if clock_speed == UNCAPPED {
+            const UNCAPPED_BATCH: u64 = 10_000;
+            return Self {
+                instructions_per_tick: UNCAPPED_BATCH,
+                time_budget_ns: 0,
+                target_accuracy_ns: 0,
+            };
+        }
+
         let target_clock_ns: u64 = (1_000_000_000.0 / clock_speed as f64) as u64;
 
         /* multiple instructions per tick, to reduce jittering */
@@ -70,113 +158,598 @@ impl EmulationSpeedParams {
 }
 
 impl Emulator {
-    pub fn new(ctx: &ggez::Context, options: &ProgramOptions) -> ggez::GameResult<Self> {
-        let sync_pair = Arc::new((Condvar::new(), Mutex::new(State::default())));
-        let sync_copy = Arc::clone(&sync_pair);
+    pub fn new(ctx: &ggez::Context, options: &ProgramOptions) -> Result<Self, Error> {
+        tracing::info!(
+            rom = %options.rom_name,
+            clock_speed = options.clock_speed,
+            clip_sprites = options.clip_sprites,
+            schip_compatibility = options.schip_compatibility,
+            display_wait = options.display_wait,
+            "starting emulation"
+        );
+
+        let stats = Arc::new(IoStats::default());
+        let internals = EmulatorInternals::new(ctx, options, Arc::clone(&stats))?;
+        internals.sound_timer.set_volume(options.volume);
+
+        let mut inputs = Inputs::default();
+        let (keyboard_source, keyboard_input) = HostInput::new("keyboard");
+        let (mouse_source, mouse_input) = HostInput::new("mouse");
+        inputs.add(keyboard_source);
+        inputs.add(mouse_source);
+        let mirror = match &options.mirror_host {
+            Some(address) => {
+                let (host, network) = MirrorHost::start(address, options.remote_keys)?;
+                inputs.add(network);
+                Some(host)
+            }
+            None => None,
+        };
+        if let Some(channel) = &options.twitch_plays {
+            inputs.add(TwitchPlays::start(channel)?);
+        }
+        for name in &options.disabled_inputs {
+            inputs.set_enabled(name, false);
+        }
+
+        let plays = PlaySession::start(ctx.fs.user_config_dir(), &options.program);
+        let mut osd = Osd::default();
+        if plays.previous().launches > 0 {
+            osd.show_for(plays.previous().describe(), Duration::from_secs(3));
+        }
 
         Ok(Emulator {
-            internals: EmulatorInternals::new(ctx, options, sync_copy)?,
-            sleeper: spin_sleep::SpinSleeper::default(),
-            keyboard_status: [false; 16],
-            update_sync_pair: sync_pair,
-            esp: EmulationSpeedParams::new(options.clock_speed),
+            internals,
+            inputs,
+            keyboard_input,
+            mouse_input,
+            mouse_bindings: options.mouse_bindings.clone(),
+            key_bindings: options.key_bindings.clone(),
+            wheel_taps: Vec::new(),
+            osd,
+            perf: PerfOverlay::new(options.clock_speed),
+            keypad: KeypadOverlay::new(options.touch_keypad),
+            zoom: Zoom::new(options.orientation),
+            palette: 0,
+            touched_key: None,
+            volume: options.volume,
+            muted: false,
+            visual_beep: options.visual_beep,
+            beeping: false,
+            rumble: match options.rumble {
+                0 => None,
+                intensity => Rumble::new(intensity),
+            },
+            last_fb: chip_8_core::EMPTY_FRAMEBUFFER,
+            last_activity: 0,
+            stale_since: Instant::now(),
+            stale_instructions: 0,
+            hung_hint_shown: false,
+            last_input: Instant::now(),
+            screen_off_after: options.screen_off_after,
+            pause_when_screen_off: options.pause_when_screen_off,
+            // a benchmark measures the machine, not how long the window had the focus
+            pause_in_background: !options.run_in_background
+                && options.bench.is_none()
+                && options.background_throttle.is_none(),
+            background_throttle: options
+                .background_throttle
+                .filter(|_| options.bench.is_none()),
+            throttled: false,
+            focused: true,
+            minimized: false,
+            ghosting_warned: false,
+            shortcuts: Shortcuts::new(&options.hotkeys),
+            host_key: None,
+            frame_dirty: true,
+            shader_error_shown: false,
+            rom_name: options.rom_name.clone(),
+            title_paused: false,
+            summary: Summary::new(&options.program),
+            plays,
+            summary_target: options.summary.clone(),
+            stats,
+            stats_target: options.stats_out.clone(),
+            bench: options.bench.map(Bench::new),
+            mirror,
+            options: options.clone(),
+            playlist: options.playlist.clone(),
+            playlist_position: 0,
+            entry_started: Instant::now(),
+            crashed: false,
         })
     }
-}
 
-impl ggez::event::EventHandler<ggez::GameError> for Emulator {
-    fn update(&mut self, _ctx: &mut ggez::Context) -> ggez::GameResult {
-        use once_cell::unsync::Lazy;
-        use std::time::SystemTime;
-        static mut TICK: Lazy<SystemTime> = Lazy::new(SystemTime::now);
+    fn note_input(&mut self) {
+        // user input is progress, even if the screen doesn't change
+        self.stale_since = Instant::now();
+        self.last_input = Instant::now();
+    }
 
-        /* time skipping (see EmulationSpeedParams documentation) */
-        {
-            // safety: update() is called only from one thread, and TICK is scoped to this function
-            let elapsed = unsafe { TICK.elapsed().unwrap().subsec_nanos() as u64 };
+    /* blank the display after a period of inactivity, to avoid burn-in on OLED panels */
+    fn is_screen_off(&self) -> bool {
+        match self.screen_off_after {
+            Some(timeout) => self.last_input.elapsed() > timeout,
+            None => false,
+        }
+    }
+
+    fn reset(&mut self) -> ggez::GameResult {
+        self.internals.reset()?;
+        tracing::info!("reset");
+        self.stale_since = Instant::now();
+        if std::mem::take(&mut self.hung_hint_shown) {
+            self.osd.unpin();
+        }
+        self.osd.show("Reset");
+
+        Ok(())
+    }
 
-            // avoiding overflow in `if (TIME_BUDGET - elapsed > TARGET_ACCURACY)`
-            if self.esp.time_budget_ns > self.esp.target_accuracy_ns + elapsed {
-                self.sleeper.sleep_ns(self.esp.time_budget_ns - elapsed);
+    /* Watchdog: a ROM that for a long time neither changes the screen, checks keys, uses the timers
+     * or the random numbers, nor waits for a key has likely crashed or reached its end, typically
+     * in a jump-to-self loop (which the core can't report, it doesn't expose the program counter).
+     */
+    fn check_progress(&mut self, waiting_for_key: bool) -> ggez::GameResult {
+        let timeout = match self.options.watchdog {
+            Some(timeout) => timeout,
+            None => return Ok(()),
+        };
+        let (fb, _) = *self
+            .internals
+            .fb_copy
+            .lock()
+            .map_err(Error::sync("framebuffer"))?;
+        let activity = self.stats.total();
+
+        if waiting_for_key || fb != self.last_fb || activity != self.last_activity {
+            self.last_fb = fb;
+            self.last_activity = activity;
+            self.stale_since = Instant::now();
+            self.stale_instructions = self.summary.instructions();
+            if std::mem::take(&mut self.hung_hint_shown) {
+                self.osd.unpin();
             }
+        } else if !self.hung_hint_shown && self.stale_since.elapsed() > timeout {
+            self.hung_hint_shown = true;
+            // all there is to trace without the program counter
+            tracing::warn!(
+                instructions = self.summary.instructions() - self.stale_instructions,
+                delay_timer = self.internals.delay_timer.get(),
+                sound_timer = self.internals.sound_timer.get(),
+                total_instructions = self.summary.instructions(),
+                "no progress for {} s: no screen changes, key checks, timer accesses or random \
+                 numbers since",
+                timeout.as_secs()
+            );
+            self.osd.pin(format!(
+                "ROM made no progress for {} s\nF5: reset, any other key: keep running",
+                timeout.as_secs()
+            ));
+        }
 
-            // safety: update() is called only from one thread, and TICK is scoped to this function
-            unsafe { *TICK = SystemTime::now() };
+        Ok(())
+    }
+
+    fn adjust_volume(&mut self, delta: i8) {
+        const VOLUME_STEP: u8 = 10;
+
+        self.volume = if delta > 0 {
+            self.volume.saturating_add(VOLUME_STEP).min(100)
+        } else {
+            self.volume.saturating_sub(VOLUME_STEP)
+        };
+        self.muted = false;
+
+        self.internals.sound_timer.set_volume(self.volume);
+        self.osd.show(format!("Volume {}%", self.volume));
+    }
+
+    fn toggle_mute(&mut self) {
+        self.muted = !self.muted;
+
+        if self.muted {
+            self.internals.sound_timer.set_volume(0);
+            self.osd.show("Muted");
+        } else {
+            self.internals.sound_timer.set_volume(self.volume);
+            self.osd.show(format!("Volume {}%", self.volume));
         }
+    }
 
-        /* game tick begins here */
-        // this function is called on the main thread by the ggez runtime, so it can't block for too long;
-        // `mtx` is shared only with `execute_next_instruction()`, which acquires it only when it
-        // can no longer block
-        let mut i: u64 = 0;
-        while i < self.esp.instructions_per_tick {
-            let (cond, mtx) = self.update_sync_pair.as_ref();
+    /* the presets replace the first entries of the configured palette, like `--palette` */
+    fn next_palette(&mut self, ctx: &ggez::Context) -> ggez::GameResult {
+        self.palette = (self.palette + 1) % (PRESETS.len() + 1);
 
-            // signal update request, unless we're still waiting from a previous iteration
-            {
-                let mut state = mtx.lock().unwrap();
+        let mut palette = self.options.palette;
+        let name = match self.palette.checked_sub(1).map(|i| PRESETS[i]) {
+            Some((name, colors)) => {
+                palette.set(&colors);
+                name
+            }
+            None => "default",
+        };
+        self.internals.screen.set_palette(ctx, palette)?;
+        self.osd.show(format!("Palette: {}", name));
+        self.frame_dirty = true;
 
-                if *state == State::WaitingForKey {
-                    break;
-                }
+        Ok(())
+    }
+
+    fn update_view(&mut self, ctx: &ggez::Context) -> ggez::GameResult {
+        self.internals.screen.set_view(ctx, self.zoom.view())?;
+        self.frame_dirty = true;
+
+        Ok(())
+    }
+
+    /* to the working directory, named after the draw that produced the screen */
+    fn dump_screen(&mut self) -> ggez::GameResult {
+        let (fb, generation) = *self
+            .internals
+            .fb_copy
+            .lock()
+            .map_err(Error::sync("framebuffer"))?;
 
-                *state = State::UpdateRequested;
+        let path = format!("screen-{}.pbm", generation);
+        match crate::headless::write_framebuffer(&path, &fb) {
+            Ok(()) => self.osd.show(format!("Screen saved to {}", path)),
+            Err(e) => {
+                tracing::warn!("cannot write {}: {}", path, e);
+                self.osd.show(format!("Cannot save the screen: {}", e));
             }
-            cond.notify_all();
+        }
 
-            // wait for feedback message
-            let state;
-            {
-                let mut feedback = mtx.lock().unwrap();
+        Ok(())
+    }
 
-                while *feedback == State::UpdateRequested {
-                    feedback = cond.wait(feedback).unwrap();
-                }
+    fn crash(&mut self, e: Error) -> ggez::GameResult {
+        if self.crashed {
+            return Ok(());
+        }
+
+        self.crashed = true;
+        self.summary.record_error(&e.to_string());
+
+        // the core doesn't expose its program counter, the instruction count is all there is
+        self.summary
+            .count_instructions(self.internals.executed.swap(0, Relaxed));
+        self.osd.pin(format!(
+            "{}\nafter {} instructions\nF5: restart, Esc: quit",
+            e,
+            self.summary.instructions()
+        ));
+
+        Ok(())
+    }
+
+    /* a new machine for `self.options`, keeping the window, the input sources and the counters */
+    fn rebuild(&mut self, ctx: &ggez::Context) -> ggez::GameResult {
+        self.internals.shutdown()?;
+        self.internals = EmulatorInternals::new(ctx, &self.options, Arc::clone(&self.stats))?;
+        self.internals
+            .sound_timer
+            .set_volume(if self.muted { 0 } else { self.volume });
+
+        self.crashed = false;
+        self.inputs.reset();
+        self.frame_dirty = true;
+        self.stale_since = Instant::now();
+        self.hung_hint_shown = false;
+        self.throttled = false;
+        self.osd.unpin();
+
+        Ok(())
+    }
+
+    /* rebuild the whole machine, the state left behind by a crashed thread can't be trusted */
+    fn restart(&mut self, ctx: &ggez::Context) -> ggez::GameResult {
+        self.rebuild(ctx)?;
+        self.osd.show("Restarted");
+        tracing::info!("restarted after a crash");
+
+        Ok(())
+    }
+
+    /* `--playlist`: swap in the next entry, back to the first after the last one */
+    fn next_rom(&mut self, ctx: &mut ggez::Context) -> ggez::GameResult {
+        if self.playlist.is_empty() {
+            return Ok(());
+        }
+
+        self.playlist_position = (self.playlist_position + 1) % self.playlist.len();
+        self.options = self.playlist[self.playlist_position].clone();
+        tracing::info!(rom = %self.options.rom_name, "next playlist entry");
+        self.rebuild(ctx)?;
+
+        if let Err(e) = self.plays.save(ctx.fs.user_config_dir()) {
+            tracing::warn!("cannot save the play history: {}", e);
+        }
+        self.plays = PlaySession::start(ctx.fs.user_config_dir(), &self.options.program);
+
+        // what the entry's flags and ROM settings can change
+        self.key_bindings = self.options.key_bindings.clone();
+        self.mouse_bindings = self.options.mouse_bindings.clone();
+        self.shortcuts = Shortcuts::new(&self.options.hotkeys);
+        self.zoom = Zoom::new(self.options.orientation);
+        self.palette = 0;
+        self.rom_name = self.options.rom_name.clone();
+        ctx.gfx
+            .set_window_title(&window_title(&self.rom_name, self.title_paused));
+
+        self.entry_started = Instant::now();
+        self.osd.show(format!(
+            "{} ({}/{})",
+            self.rom_name,
+            self.playlist_position + 1,
+            self.playlist.len()
+        ));
+
+        Ok(())
+    }
+
+    fn write_summary(&mut self) {
+        if let Some(target) = self.summary_target.take() {
+            self.summary.write(&target);
+        }
+        if let Some(target) = self.stats_target.take() {
+            self.summary.write_json(&target, &self.stats);
+        }
+    }
+
+    /* bindings are searched from the last, so that command line flags override ROM settings */
+    fn mouse_binding(&self, input: MouseInput) -> Option<KeyValue> {
+        self.mouse_bindings
+            .iter()
+            .rev()
+            .find(|(bound, _)| *bound == input)
+            .map(|(_, keycode)| *keycode)
+            .filter(|keycode| !self.options.remote_keys[*keycode as usize])
+    }
+
+    /* `--key-diagnostics`: the raw scancode of each host key, for writing key bindings, and the
+     * keypad chord held on the keyboard, flagging the combinations likely to ghost */
+    fn show_key_diagnostics(&mut self, (scancode, action): (u32, KeyAction)) {
+        let keycode = self.keypad_key(scancode);
+        tracing::info!(scancode, ?keycode, ?action, "host key");
+
+        let held = self.inputs.held_by("keyboard");
+        let chord: Vec<String> = (0..16)
+            .filter(|key| held[*key])
+            .map(|key| format!("{:X}", key))
+            .collect();
+
+        self.osd.show(format!(
+            "scancode {:#04X} {:?}: {}\nheld: {}{}",
+            scancode,
+            action,
+            keycode.map_or_else(|| "not bound".to_owned(), |key| format!("key {:X}", key)),
+            if chord.is_empty() {
+                "-".to_owned()
+            } else {
+                chord.join(" ")
+            },
+            if is_ghosting_prone(&held, &self.key_bindings) {
+                " (may ghost)"
+            } else {
+                ""
+            }
+        ));
+    }
+
+    /* keys owned by the spectators can't be pressed locally */
+    fn keypad_key(&self, scancode: u32) -> Option<KeyValue> {
+        bound_key(&self.key_bindings, scancode)
+            .filter(|keycode| !self.options.remote_keys[*keycode as usize])
+    }
+
+    /* every input source is merged here: the emulated key is pressed as long as any of them
+     * holds it, and only changes are forwarded */
+    fn forward_inputs(&mut self) -> ggez::GameResult {
+        if self.internals.direct_input && !self.crashed {
+            self.internals.keyboard.autofire(&self.internals.kb_pair)?;
+        }
 
-                state = *feedback;
+        for (keycode, action) in self.inputs.poll() {
+            // the keyboard thread may be gone
+            if self.crashed {
+                continue;
             }
 
-            match state {
-                State::WaitingForKey => break,
-                State::Ready => {}
-                State::UpdateRequested => unreachable!(),
+            match action {
+                KeyAction::Pressed => {
+                    self.note_input();
+                    self.internals.key_down_event(keycode)?;
+                }
+                KeyAction::Released => self.internals.key_up_event(keycode)?,
             }
+        }
 
-            i += 1;
+        // release the keys "tapped" by the mouse wheel, on the next poll
+        for keycode in self.wheel_taps.drain(..) {
+            self.mouse_input
+                .send((keycode, KeyAction::Released))
+                .map_err(Error::sync("mouse input"))?;
+        }
+
+        if let Some(event) = self.host_key.take() {
+            self.show_key_diagnostics(event);
+        }
+
+        if !self.ghosting_warned
+            && is_ghosting_prone(&self.inputs.held_by("keyboard"), &self.key_bindings)
+        {
+            self.ghosting_warned = true;
+            self.osd.show_for(
+                "Your keyboard may drop keys in this combination,\nconsider remapping or a gamepad",
+                Duration::from_secs(5),
+            );
         }
 
         Ok(())
     }
+}
+
+impl ggez::event::EventHandler<ggez::GameError> for Emulator {
+    fn update(&mut self, ctx: &mut ggez::Context) -> ggez::GameResult {
+        self.forward_inputs()?;
+
+        if let Some(rumble) = &mut self.rumble {
+            rumble.update(self.internals.sound_timer.is_buzzing());
+        }
+
+        // unattended playlists move on even from a crashed entry
+        let limit = self.options.advance_after;
+        if !self.playlist.is_empty() && limit.is_some_and(|t| self.entry_started.elapsed() >= t) {
+            return self.next_rom(ctx);
+        }
+
+        // errors raised by the worker threads, a crash is left on screen until a restart
+        match self.internals.errors.check() {
+            Err(e @ (Error::Panic(..) | Error::Core(..))) => return self.crash(e),
+            res => res?,
+        }
+
+        let in_background = !self.focused || self.minimized;
+        let paused = (self.pause_when_screen_off && self.is_screen_off())
+            || (self.pause_in_background && in_background);
+        self.internals.timers.set_paused(paused);
+        self.internals.set_paused(paused)?;
+        if paused != self.title_paused {
+            tracing::debug!(paused, "pause toggled");
+            self.title_paused = paused;
+            ctx.gfx
+                .set_window_title(&window_title(&self.rom_name, paused));
+        }
+        if paused {
+            return Ok(());
+        }
+
+        let throttled = self.background_throttle.is_some() && in_background;
+        if throttled != self.throttled {
+            tracing::debug!(throttled, "background throttle toggled");
+            self.throttled = throttled;
+            let percent = self
+                .background_throttle
+                .filter(|_| throttled)
+                .unwrap_or(100);
+            self.internals.throttle.store(percent, Relaxed);
+            // the timers keep their pace, the beeps would be out of step with the game
+            let volume = if self.muted || throttled {
+                0
+            } else {
+                self.volume
+            };
+            self.internals.sound_timer.set_volume(volume);
+        }
+
+        // emulation runs on its own thread, this only collects what happened since the last frame
+        let executed = self.internals.executed.swap(0, Relaxed);
+        self.summary.count_instructions(executed);
+        self.summary
+            .count_dropped_frames(self.internals.dropped_ticks.swap(0, Relaxed));
+        self.perf.record_tick(executed);
+        self.keypad.record(
+            self.internals.keyboard.pressed_keys(),
+            self.internals.keyboard.take_polled(),
+            self.internals.waiting_for_key.load(Relaxed),
+        );
+
+        if let Some(bench) = &mut self.bench {
+            bench.count_instructions(executed);
+            if bench.is_over() {
+                ctx.request_quit();
+            }
+        }
+
+        if let Some(mirror) = &mut self.mirror {
+            let (fb, generation) = *self
+                .internals
+                .fb_copy
+                .lock()
+                .map_err(Error::sync("framebuffer"))?;
+            mirror.update(&fb, generation, self.internals.sound_timer.get() > 0);
+        }
+
+        self.check_progress(self.internals.waiting_for_key.load(Relaxed))
+    }
 
     fn key_down_event(
         &mut self,
-        _ctx: &mut ggez::Context,
+        ctx: &mut ggez::Context,
         input: keyboard::KeyInput,
-        _repeated: bool,
+        repeated: bool,
     ) -> Result<(), ggez::GameError> {
-        // do not send more than one "pressed" signal if key is held
-        #[rustfmt::skip]
-        let keycode: u8 = match input.scancode {
-            0x2D => { if !self.keyboard_status[0x0] {self.keyboard_status[0x0] = true; 0x0} else { return Ok(()) } },
-            0x02 => { if !self.keyboard_status[0x1] {self.keyboard_status[0x1] = true; 0x1} else { return Ok(()) } },
-            0x03 => { if !self.keyboard_status[0x2] {self.keyboard_status[0x2] = true; 0x2} else { return Ok(()) } },
-            0x04 => { if !self.keyboard_status[0x3] {self.keyboard_status[0x3] = true; 0x3} else { return Ok(()) } },
-            0x10 => { if !self.keyboard_status[0x4] {self.keyboard_status[0x4] = true; 0x4} else { return Ok(()) } },
-            0x11 => { if !self.keyboard_status[0x5] {self.keyboard_status[0x5] = true; 0x5} else { return Ok(()) } },
-            0x12 => { if !self.keyboard_status[0x6] {self.keyboard_status[0x6] = true; 0x6} else { return Ok(()) } },
-            0x1E => { if !self.keyboard_status[0x7] {self.keyboard_status[0x7] = true; 0x7} else { return Ok(()) } },
-            0x1F => { if !self.keyboard_status[0x8] {self.keyboard_status[0x8] = true; 0x8} else { return Ok(()) } },
-            0x20 => { if !self.keyboard_status[0x9] {self.keyboard_status[0x9] = true; 0x9} else { return Ok(()) } },
-            0x2C => { if !self.keyboard_status[0xA] {self.keyboard_status[0xA] = true; 0xA} else { return Ok(()) } },
-            0x2E => { if !self.keyboard_status[0xB] {self.keyboard_status[0xB] = true; 0xB} else { return Ok(()) } },
-            0x05 => { if !self.keyboard_status[0xC] {self.keyboard_status[0xC] = true; 0xC} else { return Ok(()) } },
-            0x13 => { if !self.keyboard_status[0xD] {self.keyboard_status[0xD] = true; 0xD} else { return Ok(()) } },
-            0x21 => { if !self.keyboard_status[0xE] {self.keyboard_status[0xE] = true; 0xE} else { return Ok(()) } },
-            0x2F => { if !self.keyboard_status[0xF] {self.keyboard_status[0xF] = true; 0xF} else { return Ok(()) } },
-            _ => return Ok(()),
+        self.note_input();
+
+        let action = self.shortcuts.action(input.keycode, repeated);
+
+        if self.crashed {
+            if action == Some(Action::Reset) {
+                self.restart(ctx)?;
+            } else if action == Some(Action::NextRom) {
+                self.next_rom(ctx)?;
+            } else if input.keycode == Some(keyboard::KeyCode::Escape) {
+                ctx.request_quit();
+            }
+            return Ok(());
+        }
+
+        // dismissed, but not shown again until the ROM makes progress
+        if self.hung_hint_shown && action != Some(Action::Reset) {
+            self.osd.unpin();
+        }
+
+        match action {
+            Some(Action::Mute) => self.toggle_mute(),
+            Some(Action::Reset) => self.reset()?,
+            Some(Action::PerfOverlay) => self.perf.toggle(),
+            Some(Action::KeypadOverlay) => self.keypad.toggle(),
+            Some(Action::VolumeUp) => self.adjust_volume(1),
+            Some(Action::VolumeDown) => self.adjust_volume(-1),
+            Some(Action::DumpScreen) => self.dump_screen()?,
+            Some(Action::Palette) => self.next_palette(ctx)?,
+            Some(Action::NextRom) => self.next_rom(ctx)?,
+            Some(Action::Zoom) => {
+                self.zoom.cycle();
+                self.osd.show(if self.zoom.is_active() {
+                    format!("Zoom {}x, arrow keys to move", self.zoom.view().level)
+                } else {
+                    "Zoom off".to_owned()
+                });
+                self.update_view(ctx)?;
+            }
+            None => {}
+        }
+
+        if self.options.key_diagnostics && !repeated {
+            self.host_key = Some((input.scancode, KeyAction::Pressed));
+        }
+
+        let keycode = match self.keypad_key(input.scancode) {
+            Some(keycode) if !repeated => keycode,
+            Some(_) => return Ok(()),
+            // arrow keys move the zoomed view, unless the ROM settings bind them to the keypad
+            None => {
+                let pan = match input.keycode {
+                    Some(keyboard::KeyCode::Left) => (-1.0, 0.0),
+                    Some(keyboard::KeyCode::Right) => (1.0, 0.0),
+                    Some(keyboard::KeyCode::Up) => (0.0, -1.0),
+                    Some(keyboard::KeyCode::Down) => (0.0, 1.0),
+                    _ => return Ok(()),
+                };
+                if self.zoom.is_active() {
+                    self.zoom.pan(pan.0, pan.1);
+                    self.update_view(ctx)?;
+                }
+                return Ok(());
+            }
         };
 
-        self.internals.as_ref().key_down_event(keycode)
+        self.keyboard_input
+            .send((keycode, KeyAction::Pressed))
+            .map_err(Error::sync("keyboard input"))?;
+        Ok(())
     }
 
     fn key_up_event(
@@ -184,302 +757,741 @@ impl ggez::event::EventHandler<ggez::GameError> for Emulator {
         _ctx: &mut ggez::Context,
         input: ggez::input::keyboard::KeyInput,
     ) -> Result<(), ggez::GameError> {
-        #[rustfmt::skip]
-        let keycode: u8 = match input.scancode {
-            0x2D => { self.keyboard_status[0x0] = false; 0x0 },
-            0x02 => { self.keyboard_status[0x1] = false; 0x1 },
-            0x03 => { self.keyboard_status[0x2] = false; 0x2 },
-            0x04 => { self.keyboard_status[0x3] = false; 0x3 },
-            0x10 => { self.keyboard_status[0x4] = false; 0x4 },
-            0x11 => { self.keyboard_status[0x5] = false; 0x5 },
-            0x12 => { self.keyboard_status[0x6] = false; 0x6 },
-            0x1E => { self.keyboard_status[0x7] = false; 0x7 },
-            0x1F => { self.keyboard_status[0x8] = false; 0x8 },
-            0x20 => { self.keyboard_status[0x9] = false; 0x9 },
-            0x2C => { self.keyboard_status[0xA] = false; 0xA },
-            0x2E => { self.keyboard_status[0xB] = false; 0xB },
-            0x05 => { self.keyboard_status[0xC] = false; 0xC },
-            0x13 => { self.keyboard_status[0xD] = false; 0xD },
-            0x21 => { self.keyboard_status[0xE] = false; 0xE },
-            0x2F => { self.keyboard_status[0xF] = false; 0xF },
-            _ => return Ok(()),
+        if self.options.key_diagnostics {
+            self.host_key = Some((input.scancode, KeyAction::Released));
+        }
+
+        let keycode = match self.keypad_key(input.scancode) {
+            Some(keycode) => keycode,
+            None => return Ok(()),
         };
 
-        self.internals.as_ref().key_up_event(keycode)
+        self.keyboard_input
+            .send((keycode, KeyAction::Released))
+            .map_err(Error::sync("keyboard input"))?;
+        Ok(())
+    }
+
+    fn mouse_button_down_event(
+        &mut self,
+        ctx: &mut ggez::Context,
+        button: ggez::input::mouse::MouseButton,
+        x: f32,
+        y: f32,
+    ) -> Result<(), ggez::GameError> {
+        self.note_input();
+
+        // clicks (and touches, which ggez turns into left clicks) on the on-screen keypad
+        if button == ggez::input::mouse::MouseButton::Left {
+            let touched = self
+                .keypad
+                .key_at(ctx, x, y)
+                .filter(|keycode| !self.options.remote_keys[*keycode as usize]);
+            if let Some(keycode) = touched {
+                self.touched_key = Some(keycode);
+                self.mouse_input
+                    .send((keycode, KeyAction::Pressed))
+                    .map_err(Error::sync("mouse input"))?;
+                return Ok(());
+            }
+        }
+
+        let keycode = match MouseInput::from_button(button).and_then(|m| self.mouse_binding(m)) {
+            Some(keycode) => keycode,
+            None => return Ok(()),
+        };
+
+        self.mouse_input
+            .send((keycode, KeyAction::Pressed))
+            .map_err(Error::sync("mouse input"))?;
+        Ok(())
+    }
+
+    fn mouse_button_up_event(
+        &mut self,
+        _ctx: &mut ggez::Context,
+        button: ggez::input::mouse::MouseButton,
+        _x: f32,
+        _y: f32,
+    ) -> Result<(), ggez::GameError> {
+        // released wherever the pointer ended up
+        if button == ggez::input::mouse::MouseButton::Left {
+            if let Some(keycode) = self.touched_key.take() {
+                self.mouse_input
+                    .send((keycode, KeyAction::Released))
+                    .map_err(Error::sync("mouse input"))?;
+                return Ok(());
+            }
+        }
+
+        let keycode = match MouseInput::from_button(button).and_then(|m| self.mouse_binding(m)) {
+            Some(keycode) => keycode,
+            None => return Ok(()),
+        };
+
+        self.mouse_input
+            .send((keycode, KeyAction::Released))
+            .map_err(Error::sync("mouse input"))?;
+        Ok(())
+    }
+
+    fn mouse_wheel_event(&mut self, _ctx: &mut ggez::Context, _x: f32, y: f32) -> ggez::GameResult {
+        self.note_input();
+
+        let direction = if y > 0.0 {
+            MouseInput::WheelUp
+        } else if y < 0.0 {
+            MouseInput::WheelDown
+        } else {
+            return Ok(());
+        };
+
+        let keycode = match self.mouse_binding(direction) {
+            Some(keycode) => keycode,
+            None => return Ok(()),
+        };
+
+        // scrolling has no "release" event: hold the key until the next update
+        self.wheel_taps.push(keycode);
+        self.mouse_input
+            .send((keycode, KeyAction::Pressed))
+            .map_err(Error::sync("mouse input"))?;
+        Ok(())
     }
 
     fn draw(&mut self, ctx: &mut ggez::Context) -> ggez::GameResult {
-        self.internals.as_ref().draw(ctx)
+        let _span = tracing::trace_span!("render").entered();
+
+        if self.is_screen_off() {
+            self.frame_dirty = true;
+            let canvas = ggez::graphics::Canvas::from_frame(ctx, ggez::graphics::Color::BLACK);
+            return canvas.finish(ctx);
+        }
+
+        match self.internals.screen.reload_shader(ctx) {
+            Some(Ok(())) => {
+                if std::mem::take(&mut self.shader_error_shown) {
+                    self.osd.unpin();
+                }
+                self.osd.show("Shader loaded");
+                self.frame_dirty = true;
+            }
+            Some(Err(e)) => {
+                // wgpu errors quote the offending source, the first lines are enough on screen
+                let excerpt: Vec<&str> = e.lines().take(12).collect();
+                self.shader_error_shown = true;
+                self.osd.pin(format!(
+                    "Shader error, keeping the previous version:\n{}",
+                    excerpt.join("\n")
+                ));
+                self.frame_dirty = true;
+            }
+            None => {}
+        }
+
+        // the same state that pauses and resumes the buzzer, so that both stay in sync
+        let beeping = self.visual_beep.is_some() && self.internals.sound_timer.is_buzzing();
+        if beeping != self.beeping {
+            self.beeping = beeping;
+            self.frame_dirty = true;
+        }
+
+        // overlays are drawn on top of the previous frame, which must be repainted to clear them
+        let overlays = self.osd.is_visible()
+            || self.perf.is_visible()
+            || self.keypad.is_visible()
+            || self.zoom.is_active()
+            || beeping;
+        self.internals.draw(ctx, overlays || self.frame_dirty)?;
+        self.frame_dirty = overlays;
+
+        self.osd.draw(ctx)?;
+        self.perf.draw(ctx)?;
+        self.zoom.draw(ctx)?;
+        if let (Some(visual_beep), true) = (self.visual_beep, beeping) {
+            visual_beep.draw(ctx)?;
+        }
+        self.keypad.draw(ctx)
+    }
+
+    fn resize_event(
+        &mut self,
+        ctx: &mut ggez::Context,
+        width: f32,
+        height: f32,
+    ) -> ggez::GameResult {
+        // some platforms don't take the focus away from a minimized window
+        self.minimized = width == 0.0 || height == 0.0;
+        if self.minimized {
+            return Ok(());
+        }
+
+        self.internals.screen.resize(ctx, width, height);
+        // ggez recreates the frame image
+        self.frame_dirty = true;
+
+        Ok(())
+    }
+
+    fn focus_event(&mut self, _ctx: &mut ggez::Context, gained: bool) -> ggez::GameResult {
+        tracing::debug!(focused = gained, "window focus changed");
+        self.focused = gained;
+
+        Ok(())
+    }
+
+    fn quit_event(&mut self, ctx: &mut ggez::Context) -> Result<bool, ggez::GameError> {
+        self.internals.shutdown()?;
+        self.write_summary();
+
+        if let Some(bench) = &self.bench {
+            let report = bench.report(
+                self.internals.draws.load(Relaxed),
+                ctx.time.ticks() as u64,
+                Duration::from_nanos(self.internals.busy_ns.load(Relaxed)),
+                self.internals.waiting_for_key.load(Relaxed),
+            );
+            print!("{}", report.to_text());
+        }
+
+        if let Err(e) = self.plays.save(ctx.fs.user_config_dir()) {
+            tracing::warn!("cannot save the play history: {}", e);
+        }
+
+        let window = crate::persist::WindowState {
+            position: ctx.gfx.window().outer_position().ok().map(|p| (p.x, p.y)),
+            size: Some(ctx.gfx.drawable_size()),
+        };
+        // not worth bothering the user with, the window just opens at the default place next time
+        if let Err(e) = window.save(ctx.fs.user_config_dir()) {
+            tracing::warn!("cannot save the window geometry: {}", e);
+        }
+
+        // don't veto the quit
+        Ok(false)
+    }
+
+    fn on_error(
+        &mut self,
+        ctx: &mut ggez::Context,
+        _origin: ggez::event::ErrorOrigin,
+        e: ggez::GameError,
+    ) -> bool {
+        let message = match e {
+            ggez::GameError::CustomError(message) => message,
+            e => e.to_string(),
+        };
+        crate::error::report(&message);
+
+        // the game loop stops without a quit event
+        self.summary.record_error(&message);
+        self.write_summary();
+        let _ = self.plays.save(ctx.fs.user_config_dir());
+
+        true
     }
 }
 
 #[rustfmt::skip]
 struct EmulatorInternals {
-    _pin: std::marker::PhantomPinned,                 // self-referential
-    keyboard_send_channel: Mutex<Sender<KeyMessage>>, // communicate press/release events
+    keyboard_send_channel: Mutex<Option<Sender<KeyMessage>>>, // press/release events, None on shutdown
+    direct_input: bool,                                       // no keyboard thread, nor channel
+    keyboard: Arc<KeyboardManager>,
+    kb_pair: Arc<KeyboardSyncPair>,                           // to cancel a wait for a key
     screen: Screen,
-    core: Mutex<Chip8<'static>>,
-    fb_copy: Mutex<FrameBuffer>,
-    update_sync_pair: Arc<(Condvar, Mutex<State>)>,
-    // dyn Fn(...) is !Unpin
-    time_setter:  Pin<Box<dyn Fn(u8)         + 'static + Send + Sync>>,
-    time_getter:  Pin<Box<dyn Fn()   -> u8   + 'static + Send + Sync>>,
-    sound_setter: Pin<Box<dyn Fn(u8)         + 'static + Send + Sync>>,
-    next_rand:    Pin<Box<dyn Fn()   -> u8   + 'static + Send + Sync>>,
-    is_pressed:   Pin<Box<dyn Fn(u8) -> bool + 'static + Send + Sync>>,
-    wait_for_key: Pin<Box<dyn Fn()   -> u8   + 'static + Send + Sync>>,
-    draw_signal:  Pin<Box<dyn Fn()           + 'static + Send + Sync>>,
+    sound_timer: Arc<SoundTimer>,
+    delay_timer: Arc<DelayTimer>,
+    timers: Arc<TimerDriver>,
+    program: Vec<u8>,
+    clip_sprites: bool,
+    schip_compatibility: bool,
+    fb_copy: Mutex<(FrameBuffer, u64)>,                       // framebuffer, generation
+    control: (Condvar, Mutex<(State, bool)>),                 // state, reset requested
+    esp: EmulationSpeedParams,
+    display_wait: bool,
+    waiting_for_key: Arc<AtomicBool>,                         // the core is blocked on Fx0A
+    executed: AtomicU64,                                      // instructions, reset by the main thread
+    dropped_ticks: AtomicU64,                                 // same, ticks that overran their budget
+    busy_ns: AtomicU64,                                       // time spent executing batches
+    throttle: AtomicU8,                                       // percentage of the clock speed
+    errors: Arc<ErrorSlot>,
+    workers: Mutex<Vec<JoinHandle<()>>>,                      // joined on shutdown
+    drawn: Arc<AtomicBool>,                                   // set by the core on every draw
+    draws: Arc<AtomicU64>,                                    // framebuffer generation counter
+    stats: Arc<IoStats>,                                      // counted by the core's callbacks
 }
 
 impl EmulatorInternals {
     fn new(
         ctx: &ggez::Context,
         options: &ProgramOptions,
-        sync_pair: Arc<(Condvar, Mutex<State>)>,
-    ) -> ggez::GameResult<Pin<Arc<Self>>> {
-        let screen = Screen::new(ctx)?;
+        stats: Arc<IoStats>,
+    ) -> Result<Arc<Self>, Error> {
+        let screen = Screen::new(ctx, options)?;
 
         /* create system sound */
-        let waveform = std::include_bytes!("../resources/sound.ogg");
-        let sound_data = ggez::audio::SoundData::from_bytes(waveform);
-        let mut sound = ggez::audio::Source::from_data(ctx, sound_data)?;
-        sound.set_repeat(true);
-        sound.play_later()?; // seems there's no way to initialize the playback in a paused state
-        sound.pause();
+        let sound = Buzzer::new(ctx, options.beep, options.audio)?;
+
+        let errors = Arc::new(ErrorSlot::default());
 
         /* timers generation and initialization */
         let sound_timer = Arc::new(SoundTimer::new(sound));
         let delay_timer = Arc::new(DelayTimer::new());
-        let st = Arc::clone(&sound_timer);
-        let dt = Arc::clone(&delay_timer);
-        std::thread::spawn(move || st.start());
-        std::thread::spawn(move || dt.start());
+        let timers = Arc::new(TimerDriver::new(
+            Arc::clone(&delay_timer),
+            Arc::clone(&sound_timer),
+        ));
+        let td = Arc::clone(&timers);
+        let timer_errors = Arc::clone(&errors);
+        let timer_thread = std::thread::spawn(move || {
+            let res = crate::error::catch_panic("timer", || {
+                td.start();
+                Ok(())
+            });
+            if let Err(e) = res {
+                timer_errors.raise(e);
+            }
+        });
 
-        let st = Arc::clone(&sound_timer);
-        let dt1 = Arc::clone(&delay_timer);
-        let dt2 = Arc::clone(&delay_timer);
+        let (keyboard, kb_pair) = KeyboardManager::new(
+            &options.turbo,
+            options.wait_for_release,
+            Arc::clone(&errors),
+        );
+        let mut workers = vec![timer_thread];
+        // `--direct-input`: the main thread hands the events to `keyboard` itself
+        let keyboard_send_channel = (!options.direct_input).then(|| {
+            let (tx, rx): (Sender<KeyMessage>, Receiver<KeyMessage>) = mpsc::channel();
+            workers.push(keyboard.spawn(rx, &kb_pair));
+            tx
+        });
+        let res = Arc::new(Self {
+            keyboard_send_channel: Mutex::new(keyboard_send_channel),
+            direct_input: options.direct_input,
+            keyboard,
+            kb_pair,
+            screen,
+            sound_timer: Arc::clone(&sound_timer),
+            delay_timer: Arc::clone(&delay_timer),
+            timers,
+            program: options.program.clone(),
+            clip_sprites: options.clip_sprites,
+            schip_compatibility: options.schip_compatibility,
+            fb_copy: Mutex::new((chip_8_core::EMPTY_FRAMEBUFFER, 0)),
+            control: (Condvar::new(), Mutex::new((State::default(), false))),
+            esp: EmulationSpeedParams::new(options.clock_speed),
+            display_wait: options.display_wait,
+            waiting_for_key: Arc::new(AtomicBool::new(false)),
+            executed: AtomicU64::new(0),
+            dropped_ticks: AtomicU64::new(0),
+            busy_ns: AtomicU64::new(0),
+            throttle: AtomicU8::new(100),
+            errors,
+            workers: Mutex::new(workers),
+            drawn: Arc::new(AtomicBool::new(false)),
+            draws: Arc::new(AtomicU64::new(0)),
+            stats,
+        });
 
-        /* generate and immediately discard random u8 to initialize the thread-local PRNG state,
-         * so that the first call to `random()` during emulation is not slower than subsequent ones
-         * (rand::ThreadRng is lazily initialized); if `black_box()` is ignored by the compiler
-         * the very first call to `random()` that is actually used will be slower
-         */
-        let _ = std::hint::black_box(rand::random::<u8>());
+        let temp = res.clone();
+        let emulator_thread = std::thread::spawn(move || {
+            if let Err(e) = crate::error::catch_panic("emulator", || temp.start()) {
+                temp.errors.raise(e);
+            }
+        });
+        res.workers
+            .lock()
+            .map_err(Error::sync("worker threads"))?
+            .push(emulator_thread);
 
-        let callbacks = IOCallbacks {
-            sound_setter: &|_x| {},
-            time_setter: &|_x| {},
-            time_getter: &|| 0,
-            is_pressed: &|_x| false,
-            wait_for_key: &|| 0,
-            rng: &|| 0,
-            draw_signal: &|| (),
-        };
+        Ok(res)
+    }
 
-        let (tx, rx): (Sender<KeyMessage>, Receiver<KeyMessage>) = mpsc::channel();
-        let (keyboard, kb_pair) = KeyboardManager::new(rx);
-        let kb1 = Arc::clone(&keyboard);
-        let pair = Arc::clone(&sync_pair);
+    /* restart the program from scratch, waiting for the emulator thread to replace its core */
+    fn reset(&self) -> Result<(), Error> {
+        let (cond, mtx) = &self.control;
+        mtx.lock().map_err(Error::sync("emulator state"))?.1 = true;
+        cond.notify_all();
 
-        // IMPORTANT: the wait_for_key callback must update the State mutex in the calling thread
-        // (i.e. it shouldn't spawn a new thread and modify the State mutex from it)
-        let wait_for_key = move || {
-            // signal the emulator thread
-            let (cond, mtx) = pair.as_ref();
-            {
-                let mut state = mtx.lock().unwrap();
-                *state = State::WaitingForKey;
+        // The core can't be interrupted in the middle of Fx0A, so the wait is cancelled instead:
+        // the instructions run after it with a dummy key don't matter, the core is thrown away
+        // right after. The wait may start while we're waiting here, hence the timeout.
+        loop {
+            // the emulator thread is gone, the error is picked up by the next update
+            if self.errors.check().is_err() {
+                return Ok(());
+            }
+            if self.waiting_for_key.load(Relaxed) {
+                self.cancel_key_wait()?;
             }
-            cond.notify_all();
 
-            // signal the keyboard thread
-            let (kb_cond, kb_mtx) = kb_pair.as_ref();
-            {
-                let mut kb_state = kb_mtx.lock().unwrap();
-                *kb_state = KeyboardState::Waiting;
+            let control = mtx.lock().map_err(Error::sync("emulator state"))?;
+            if !control.1 || control.0 == State::ShuttingDown {
+                return Ok(());
             }
-            kb_cond.notify_all();
-
-            let mut kb_state = kb_mtx.lock().unwrap();
-            let res;
-            loop {
-                kb_state = kb_cond.wait(kb_state).unwrap();
-                match *kb_state {
-                    KeyboardState::Normal => continue,
-                    KeyboardState::Waiting => continue,
-                    KeyboardState::PressedWhileWaiting(val) => {
-                        *kb_state = KeyboardState::Normal;
-                        res = val;
-                        break;
-                    }
+            let _ = cond
+                .wait_timeout(control, Duration::from_millis(1))
+                .map_err(Error::sync("emulator state"))?;
+        }
+    }
+
+    /* the waiting flag is raised slightly before the wait starts, so this may have to be retried */
+    fn cancel_key_wait(&self) -> Result<(), Error> {
+        let (cond, mtx) = self.kb_pair.as_ref();
+        {
+            let mut state = mtx.lock().map_err(Error::sync("keyboard state"))?;
+            match *state {
+                KeyboardState::Waiting | KeyboardState::HeldWhileWaiting(_) => {
+                    *state = KeyboardState::Cancelled
                 }
+                _ => return Ok(()),
             }
-            kb_cond.notify_all();
+        }
+        cond.notify_all();
 
-            res
+        Ok(())
+    }
+
+    /* returns whether the emulator thread was paused before the call */
+    fn set_paused(&self, paused: bool) -> Result<bool, Error> {
+        let (cond, mtx) = &self.control;
+        let was_paused = {
+            let state = &mut mtx.lock().map_err(Error::sync("emulator state"))?.0;
+            let was_paused = *state == State::Paused;
+            *state = match *state {
+                State::Running | State::Paused if paused => State::Paused,
+                State::Running | State::Paused => State::Running,
+                State::ShuttingDown => State::ShuttingDown,
+            };
+            was_paused
         };
+        cond.notify_all();
 
-        let res = Arc::pin(Self {
-            _pin: std::marker::PhantomPinned::default(),
-            keyboard_send_channel: Mutex::new(tx),
-            screen,
-            fb_copy: Mutex::new(chip_8_core::EMPTY_FRAMEBUFFER),
-            update_sync_pair: sync_pair,
-            sound_setter: Box::pin(move |x| st.set(x)),
-            time_setter: Box::pin(move |x| dt1.set(x)),
-            time_getter: Box::pin(move || dt2.get()),
-            next_rand: Box::pin(rand::random::<u8>),
-            is_pressed: Box::pin(move |x| kb1.is_pressed(x)),
-            wait_for_key: Box::pin(wait_for_key),
-            draw_signal: Box::pin(|| {}),
-            core: Mutex::new(Chip8::new(
-                &[],
-                callbacks,
-                options.clip_sprites,
-                options.schip_compatibility,
-            )),
-        });
+        Ok(was_paused)
+    }
+
+    /* stop the worker threads and wait for them to exit */
+    fn shutdown(&self) -> Result<(), Error> {
+        // the emulator thread, either idle or (through the keyboard thread) blocked on a keypress
+        {
+            let (cond, mtx) = &self.control;
+            mtx.lock().map_err(Error::sync("emulator state"))?.0 = State::ShuttingDown;
+            cond.notify_all();
+        }
+
+        // the keyboard thread exits as soon as the channel is closed
+        self.keyboard_send_channel
+            .lock()
+            .map_err(Error::sync("keyboard channel"))?
+            .take();
+        if self.direct_input {
+            KeyboardManager::stop(&self.kb_pair);
+        }
+
+        self.timers.stop();
+
+        let workers =
+            std::mem::take(&mut *self.workers.lock().map_err(Error::sync("worker threads"))?);
+        for worker in workers {
+            worker.join().map_err(|_| Error::Sync("worker threads"))?;
+        }
 
-        /* Safety:
-         * Lifetime: we are pointing to members of ref to construct `core`, another member of ref;
-         * neither the closures nor `core` can be invalidated after construction (we return a pinned
-         * emulator without mutable projections to either).
-         *
-         * Address stability: currently (rustc 1.68.2) the T in Pin<Box<T>> doesn't get marked as
-         * `noalias` in the LLVM representation (if T is !Unpin); therefore, relying on address
-         * stability for Pin<Box<T>> is correct as long as this keeps being the case (and it's
-         * currently done in many crates with self-referential structs), but until the aliasing
-         * rules aren't standardised, this is technically UB.
-         *
-         * https://github.com/rust-lang/unsafe-code-guidelines/issues/326
-         * https://github.com/rust-lang/unsafe-code-guidelines/issues/148
+        Ok(())
+    }
+
+    /* Emulator thread loop: instructions are executed in ticks of `instructions_per_tick`, each
+     * scheduled `time_budget_ns` after the previous one, independently of the display refresh
+     * rate; the main thread only presents the latest framebuffer.
+     *
+     * The core borrows its callbacks, so both live on this stack frame, and only this thread ever
+     * touches the core: resets are requested through `control`.
+     */
+    fn start(&self) -> Result<(), Error> {
+        let _span = tracing::info_span!("core").entered();
+        tracing::debug!(
+            instructions_per_tick = self.esp.instructions_per_tick,
+            time_budget_ns = self.esp.time_budget_ns,
+            "emulator thread started"
+        );
+
+        /* generate and immediately discard random u8 to initialize the thread-local PRNG state,
+         * so that the first call to `random()` during emulation is not slower than subsequent ones
+         * (rand::ThreadRng is lazily initialized); if `black_box()` is ignored by the compiler
+         * the very first call to `random()` that is actually used will be slower
          */
-        let rng = unsafe {
-            &*(res.next_rand.as_ref().get_ref() as *const (dyn Fn() -> u8 + Send + Sync))
+        let _ = std::hint::black_box(rand::random::<u8>());
+
+        let stats = &self.stats;
+        let sound_setter = |x| {
+            IoStats::count(&stats.sound_writes);
+            self.sound_timer.set(x)
         };
-        let sound_setter =
-            unsafe { &*(res.sound_setter.as_ref().get_ref() as *const (dyn Fn(u8) + Send + Sync)) };
-        let time_setter =
-            unsafe { &*(res.time_setter.as_ref().get_ref() as *const (dyn Fn(u8) + Send + Sync)) };
-        let time_getter = unsafe {
-            &*(res.time_getter.as_ref().get_ref() as *const (dyn Fn() -> u8 + Send + Sync))
+        let time_setter = |x| {
+            IoStats::count(&stats.delay_writes);
+            self.delay_timer.set(x)
         };
-        let is_pressed = unsafe {
-            &*(res.is_pressed.as_ref().get_ref() as *const (dyn Fn(u8) -> bool + Send + Sync))
+        let time_getter = || {
+            IoStats::count(&stats.delay_reads);
+            self.delay_timer.get()
         };
-        let wait_for_key = unsafe {
-            &*(res.wait_for_key.as_ref().get_ref() as *const (dyn Fn() -> u8 + Send + Sync))
+        let next_rand = || {
+            IoStats::count(&stats.random_numbers);
+            rand::random::<u8>()
         };
-        let draw_signal =
-            unsafe { &*(res.draw_signal.as_ref().get_ref() as *const (dyn Fn() + Send + Sync)) };
-
-        let callbacks = IOCallbacks {
-            sound_setter,
-            time_setter,
-            time_getter,
-            is_pressed,
-            wait_for_key,
-            rng,
-            draw_signal,
+        let is_pressed = |x| {
+            IoStats::count(&stats.key_polls);
+            self.keyboard.is_pressed(x)
         };
-
-        {
-            let mut x = res.core.lock().unwrap();
-            let y = &mut *x;
-            *y = Chip8::new(
-                &options.program[..],
+        // the core can't handle errors raised by its callbacks: report them to the main thread, and
+        // stop after the current instruction
+        let wait_for_key = || {
+            IoStats::count(&stats.key_waits);
+            wait_for_keypress(&self.waiting_for_key, &self.kb_pair).unwrap_or_else(|e| {
+                self.errors.raise(e);
+                0
+            })
+        };
+        let draw_signal = || {
+            IoStats::count(&stats.draws);
+            self.drawn.store(true, Relaxed);
+            self.draws.fetch_add(1, Relaxed);
+        };
+        let new_core = || {
+            let callbacks = IOCallbacks {
+                sound_setter: &sound_setter,
+                time_setter: &time_setter,
+                time_getter: &time_getter,
+                is_pressed: &is_pressed,
+                wait_for_key: &wait_for_key,
+                rng: &next_rand,
+                draw_signal: &draw_signal,
+            };
+            Chip8::new(
+                &self.program[..],
                 callbacks,
-                options.clip_sprites,
-                options.schip_compatibility,
-            );
-        }
-
-        let temp = res.clone();
-        std::thread::spawn(move || {
-            let x = temp.as_ref();
-            x.start();
-        });
+                self.clip_sprites,
+                self.schip_compatibility,
+            )
+        };
+        let mut core = new_core();
 
-        Ok(res)
-    }
+        let (cond, mtx) = &self.control;
+        let sleeper = spin_sleep::SpinSleeper::default();
+        let time_budget = Duration::from_nanos(self.esp.time_budget_ns);
+        let target_accuracy = Duration::from_nanos(self.esp.target_accuracy_ns);
 
-    fn start(self: Pin<&Self>) {
-        let (cond, mtx) = self.update_sync_pair.as_ref();
+        // display interrupts are counted from here (display wait mode)
+        let vblank_epoch = Instant::now();
+        let mut next_tick = Instant::now();
 
-        /* emulator thread loop */
         loop {
-            // wait for next "update" signal
+            // a callback failed during the previous tick
+            self.errors.check()?;
+
             {
-                let mut state = mtx.lock().unwrap();
+                let mut control = mtx.lock().map_err(Error::sync("emulator state"))?;
+
+                if control.0 == State::Paused && !control.1 {
+                    while control.0 == State::Paused && !control.1 {
+                        control = cond.wait(control).map_err(Error::sync("emulator state"))?;
+                    }
+                    // don't try to catch up with the time spent paused
+                    next_tick = Instant::now();
+                }
 
-                // still waiting from a previous iteration?
-                if *state == State::WaitingForKey {
-                    drop(state);
-                    std::thread::yield_now();
+                if control.0 == State::ShuttingDown {
+                    tracing::debug!("emulator thread stopped");
+                    return Ok(());
+                }
+
+                // requested by `reset`, which is waiting for it; if paused, back to waiting
+                if control.1 {
+                    core = new_core();
+                    self.delay_timer.set(0);
+                    self.sound_timer.set(0);
+                    let generation = self.draws.fetch_add(1, Relaxed) + 1;
+                    *self.fb_copy.lock().map_err(Error::sync("framebuffer"))? =
+                        (*core.fb_ref(), generation);
+
+                    control.1 = false;
+                    cond.notify_all();
                     continue;
                 }
+            }
+
+            /* emulator tick */
+            let batch_start = Instant::now();
+            let (executed, drawn) =
+                self.execute_batch(&mut core, self.esp.instructions_per_tick)?;
+            self.busy_ns
+                .fetch_add(batch_start.elapsed().as_nanos() as u64, Relaxed);
+            self.executed.fetch_add(executed, Relaxed);
+
+            // display wait: nothing runs after a draw until the next display interrupt
+            let vblank = drawn.then(|| {
+                let frames = vblank_epoch.elapsed().as_nanos() / FRAME_NS + 1;
+                vblank_epoch + Duration::from_nanos((frames * FRAME_NS) as u64)
+            });
 
-                while *state != State::UpdateRequested {
-                    state = cond.wait(state).unwrap();
+            /* time skipping (see EmulationSpeedParams documentation) */
+            next_tick += time_budget;
+            if let Some(vblank) = vblank {
+                next_tick = next_tick.max(vblank);
+            }
+
+            // background throttle: idle long enough for the tick to take that share of the time
+            let throttle = self.throttle.load(Relaxed) as u32;
+            if throttle < 100 {
+                let idle = |busy: Duration| busy * (100 - throttle) / throttle;
+                if time_budget.is_zero() {
+                    // uncapped ticks have no schedule, only the time they took
+                    next_tick = Instant::now() + idle(batch_start.elapsed());
+                } else {
+                    next_tick += idle(time_budget);
                 }
             }
 
-            // will block on `wait_for_key`
-            self.execute_next_instruction();
+            let now = Instant::now();
+            if next_tick > now + target_accuracy {
+                sleeper.sleep(next_tick - now);
+            } else if !time_budget.is_zero() && now > next_tick + time_budget {
+                // more than a whole tick late (e.g. after waiting for a key): start over from now
+                tracing::trace!(
+                    late_ns = (now - next_tick).as_nanos() as u64,
+                    "tick dropped"
+                );
+                self.dropped_ticks.fetch_add(1, Relaxed);
+                next_tick = now;
+            }
         }
     }
 
-    fn draw(self: Pin<&Self>, ctx: &mut ggez::Context) -> ggez::GameResult {
-        let fb = self.fb_copy.lock().unwrap();
-        self.as_ref().pin_get_screen().draw(ctx, &fb)
+    fn draw(&self, ctx: &mut ggez::Context, repaint: bool) -> ggez::GameResult {
+        let (fb, generation) = *self.fb_copy.lock().map_err(Error::sync("framebuffer"))?;
+        self.screen.draw(ctx, &fb, generation, repaint)
     }
 
-    fn key_down_event(self: Pin<&Self>, keycode: u8) -> Result<(), ggez::GameError> {
-        self.keyboard_send_channel
-            .lock()
-            .unwrap()
-            .send((keycode, KeyAction::Pressed))
-            .unwrap();
+    fn key_down_event(&self, keycode: u8) -> Result<(), ggez::GameError> {
+        self.send_key_message((keycode, KeyAction::Pressed))
+    }
 
-        Ok(())
+    fn key_up_event(&self, keycode: u8) -> Result<(), ggez::GameError> {
+        self.send_key_message((keycode, KeyAction::Released))
     }
 
-    fn key_up_event(self: Pin<&Self>, keycode: u8) -> Result<(), ggez::GameError> {
-        self.keyboard_send_channel
+    fn send_key_message(&self, message: KeyMessage) -> Result<(), ggez::GameError> {
+        if self.direct_input {
+            return Ok(self.keyboard.handle(message, &self.kb_pair)?);
+        }
+
+        // events arriving after shutdown are dropped
+        if let Some(tx) = self
+            .keyboard_send_channel
             .lock()
-            .unwrap()
-            .send((keycode, KeyAction::Released))
-            .unwrap();
+            .map_err(Error::sync("keyboard channel"))?
+            .as_ref()
+        {
+            tx.send(message).map_err(Error::sync("keyboard channel"))?;
+        }
 
         Ok(())
     }
 
-    fn pin_get_screen(self: Pin<&Self>) -> &Screen {
-        &self.get_ref().screen
-    }
+    /* Execute up to `count` instructions, returning how many were executed and whether the batch
+     * was cut short by a draw (display wait mode only).
+     */
+    fn execute_batch(&self, core: &mut Chip8, count: u64) -> Result<(u64, bool), Error> {
+        let mut copied = self.fb_copy.lock().map_err(Error::sync("framebuffer"))?.1;
+
+        let mut executed = 0;
+        while executed < count {
+            // will block on `wait_for_key`; a panic of the core is caught here rather than by the
+            // thread, so that the instructions executed before it are accounted for
+            let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                core.execute_next_instruction()
+            }));
+            if let Err(payload) = res {
+                self.executed.fetch_add(executed, Relaxed);
+                let message = chip_8_desktop::session::panic_message(&*payload);
+                return Err(Error::Core(message));
+            }
+            executed += 1;
 
-    fn execute_next_instruction(self: Pin<&Self>) {
-        // will block on `wait_for_key`
-        {
-            let mut core_mtx = self.core.lock().unwrap();
-            core_mtx.execute_next_instruction();
             /* update framebuffer */
-            // updating at every instruction has been measured to have no impact whatsoever, and
-            // it's by far the easiest way to make sure that the framebuffer update issued between
-            // the start of an emulator tick and a `wait_for_key` are drawn while we're waiting
-            {
-                let mut fb_mtx = self.fb_copy.lock().unwrap();
-                *fb_mtx = *core_mtx.fb_ref();
+            // copied right after every draw rather than at the end of the batch, so that the
+            // updates issued before a `wait_for_key` are shown while we're waiting; checking the
+            // draw counter is a single atomic load, the lock is only taken when something changed
+            let generation = self.draws.load(Relaxed);
+            if generation != copied {
+                *self.fb_copy.lock().map_err(Error::sync("framebuffer"))? =
+                    (*core.fb_ref(), generation);
+                copied = generation;
+            }
+
+            if self.display_wait && self.drawn.swap(false, Relaxed) {
+                return Ok((executed, true));
             }
         }
 
-        // `mtx` is shared with the main thread, so it's important to lock it only once we're sure
-        // we can no longer block
-        let (cond, mtx) = self.update_sync_pair.as_ref();
-        {
-            let mut state = mtx.lock().unwrap();
-            *state = State::Ready;
+        Ok((executed, false))
+    }
+}
+
+// IMPORTANT: the wait_for_key callback must block the calling thread (i.e. the emulator thread)
+// until a key is pressed, since the core expects the key as its return value
+fn wait_for_keypress(
+    waiting: &AtomicBool,
+    kb_pair: &(Condvar, Mutex<KeyboardState>),
+) -> Result<KeyValue, Error> {
+    // signal the main thread, which must not treat the unchanging screen as a hang
+    waiting.store(true, Relaxed);
+    let res = wait_for_keyboard(kb_pair);
+    waiting.store(false, Relaxed);
+
+    res
+}
+
+fn wait_for_keyboard(kb_pair: &(Condvar, Mutex<KeyboardState>)) -> Result<KeyValue, Error> {
+    // signal the keyboard thread
+    let (kb_cond, kb_mtx) = kb_pair;
+    {
+        let mut kb_state = kb_mtx.lock().map_err(Error::sync("keyboard state"))?;
+        if *kb_state != KeyboardState::ShuttingDown {
+            *kb_state = KeyboardState::Waiting;
         }
-        cond.notify_all();
     }
+    kb_cond.notify_all();
+
+    let mut kb_state = kb_mtx.lock().map_err(Error::sync("keyboard state"))?;
+    let res;
+    loop {
+        match *kb_state {
+            KeyboardState::Normal => {}
+            KeyboardState::Waiting => {}
+            KeyboardState::HeldWhileWaiting(_) => {}
+            KeyboardState::PressedWhileWaiting(val) => {
+                *kb_state = KeyboardState::Normal;
+                res = val;
+                break;
+            }
+            // the core is about to be replaced, the value is irrelevant
+            KeyboardState::Cancelled => {
+                *kb_state = KeyboardState::Normal;
+                res = 0;
+                break;
+            }
+            // no key is coming, the value is irrelevant since the emulator thread is exiting
+            KeyboardState::ShuttingDown => {
+                res = 0;
+                break;
+            }
+        }
+
+        kb_state = kb_cond
+            .wait(kb_state)
+            .map_err(Error::sync("keyboard state"))?;
+    }
+    kb_cond.notify_all();
+
+    Ok(res)
 }
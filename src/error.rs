@@ -0,0 +1,152 @@
+use chip_8_desktop::session::panic_message;
+pub use chip_8_desktop::session::MAX_ROM_SIZE;
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub enum Error {
+    InvalidArguments,
+    UnknownFlag(String),
+    UnexpectedArgument(String),
+    MissingValue(String),                       // flag
+    ConflictingFlags(String, String),           // flags
+    InvalidValue(String, String, &'static str), // flag, value, what was expected
+    RomNotFound(String),
+    RomTooLarge(String, usize),
+    Audio(String),
+    Gpu(String),
+    LogFile(String, String),        // path, reason
+    InvalidSidecar(String, String), // path, reason
+    InvalidArchive(String, String), // path, reason
+    Download(String, String),       // URL, reason
+    Playlist(String, String),       // path, reason
+    Background(String, String),     // path, reason
+    Reference(String, String),      // path, reason
+    Mirror(String),
+    Chat(String),
+    Terminal(String),
+    // a worker thread died, leaving behind a poisoned mutex or a disconnected channel
+    Sync(&'static str),
+    Panic(&'static str, String), // thread, panic message; the emulator can be restarted
+    Core(String),                // the core panicked on an instruction, same as `Panic`
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidArguments => write!(f, "Invalid arguments!"),
+            Error::UnknownFlag(flag) => write!(f, "Unknown flag \"{}\"", flag),
+            Error::UnexpectedArgument(arg) => {
+                write!(
+                    f,
+                    "Unexpected argument \"{}\", only one ROM can be run",
+                    arg
+                )
+            }
+            Error::MissingValue(flag) => write!(f, "Flag \"{}\" requires a value", flag),
+            Error::ConflictingFlags(a, b) => {
+                write!(f, "Flags \"{}\" and \"{}\" can't be used together", a, b)
+            }
+            Error::InvalidValue(flag, value, expected) => write!(
+                f,
+                "Invalid value \"{}\" for flag \"{}\", expected {}",
+                value, flag, expected
+            ),
+            Error::RomNotFound(path) => write!(f, "Cannot read ROM \"{}\"", path),
+            Error::RomTooLarge(path, size) => write!(
+                f,
+                "ROM \"{}\" is too large ({} bytes, at most {} are supported)",
+                path, size, MAX_ROM_SIZE
+            ),
+            Error::Audio(e) => write!(f, "Audio initialization failed: {}", e),
+            Error::Gpu(e) => write!(f, "Graphics initialization failed: {}", e),
+            Error::InvalidSidecar(path, e) => write!(f, "Invalid ROM settings \"{}\": {}", path, e),
+            Error::Playlist(path, e) => write!(f, "Invalid playlist \"{}\": {}", path, e),
+            Error::Download(url, e) => write!(f, "Cannot download \"{}\": {}", url, e),
+            Error::InvalidArchive(path, e) => write!(f, "Cannot load ROM from \"{}\": {}", path, e),
+            Error::Background(path, e) => {
+                write!(f, "Cannot load background image \"{}\": {}", path, e)
+            }
+            Error::Reference(path, e) => {
+                write!(f, "Cannot use reference image \"{}\": {}", path, e)
+            }
+            Error::Mirror(e) => write!(f, "Mirroring failed: {}", e),
+            Error::Chat(e) => write!(f, "Cannot connect to the Twitch chat: {}", e),
+            Error::Terminal(e) => write!(f, "Terminal error: {}", e),
+            Error::LogFile(path, e) => write!(f, "Cannot open log file \"{}\": {}", path, e),
+            Error::Sync(what) => write!(f, "Emulator thread stopped unexpectedly ({})", what),
+            Error::Panic(thread, message) => {
+                write!(f, "The {} thread crashed: {}", thread, message)
+            }
+            Error::Core(message) => {
+                write!(
+                    f,
+                    "The program hit an instruction the core can't run: {}",
+                    message
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Error {
+    /* to be used as `mtx.lock().map_err(Error::sync("what"))?` */
+    pub fn sync<T>(what: &'static str) -> impl FnOnce(T) -> Self {
+        move |_| Error::Sync(what)
+    }
+}
+
+impl From<Error> for ggez::GameError {
+    fn from(e: Error) -> Self {
+        ggez::GameError::CustomError(e.to_string())
+    }
+}
+
+/* first fatal error raised by a worker thread, reported by the main thread on its next update */
+#[derive(Default)]
+pub struct ErrorSlot(once_cell::sync::OnceCell<Error>);
+
+impl ErrorSlot {
+    pub fn raise(&self, e: Error) {
+        tracing::error!("{}", e);
+
+        // only the first error is relevant, the following ones are likely a consequence of it
+        let _ = self.0.set(e);
+    }
+
+    pub fn check(&self) -> Result<(), Error> {
+        match self.0.get() {
+            Some(e) => Err(e.clone()),
+            None => Ok(()),
+        }
+    }
+}
+
+/* run the body of a worker thread, turning a panic into an error reported to the main thread,
+ * rather than letting the thread die unnoticed */
+pub fn catch_panic(
+    thread: &'static str,
+    f: impl FnOnce() -> Result<(), Error>,
+) -> Result<(), Error> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(f))
+        .unwrap_or_else(|payload| Err(Error::Panic(thread, panic_message(&*payload))))
+}
+
+/* print the error, and show it in a message box on platforms where there's no console */
+pub fn report(message: &str) {
+    eprintln!("ERROR: {}", message);
+
+    #[cfg(any(windows, target_os = "macos"))]
+    rfd::MessageDialog::new()
+        .set_level(rfd::MessageLevel::Error)
+        .set_title("Chip-8 Emulator")
+        .set_description(message)
+        .set_buttons(rfd::MessageButtons::Ok)
+        .show();
+}
+
+pub fn exit_with(e: Error) -> ! {
+    report(&e.to_string());
+    std::process::exit(1)
+}
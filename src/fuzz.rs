@@ -0,0 +1,86 @@
+use crate::session::{self, Config, MAX_ROM_SIZE};
+use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+/* bound of a single run, a ROM looping forever is expected */
+pub const MAX_CYCLES: u64 = 10_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub cycle: u64, // applied once this many instructions have been executed
+    pub key: u8,
+    pub pressed: bool,
+}
+
+/* Runs `program` for at most `MAX_CYCLES` instructions, feeding it the events of `script` (sorted
+ * by cycle). A key wait that finds no key held skips ahead to the next scripted press, as a
+ * frontend blocking on the keyboard would, and gives up once the script is over. A faulty
+ * instruction (invalid opcode, stack overflow...) ends the run like the frontends do; panics
+ * escaping `Session::try_step` and hangs are the failures a fuzzer looks for.
+ */
+pub fn run(program: &[u8], script: &[KeyEvent], config: &Config) {
+    let program = &program[..program.len().min(MAX_ROM_SIZE)];
+    let applied = AtomicUsize::new(0); // events up to here are applied, or due after this step
+
+    let wait_for_key = || {
+        let first = applied.load(Relaxed);
+        let press = first + script[first..].iter().position(|event| event.pressed)?;
+        applied.store(press + 1, Relaxed);
+        Some(script[press].key & 0xF)
+    };
+
+    session::with_key_wait(program, config, wait_for_key, |session| {
+        let mut next = 0; // first event not applied to the session yet
+        while session.cycles() < MAX_CYCLES {
+            if session.try_step().is_err() {
+                return;
+            }
+
+            let due = script[next..]
+                .iter()
+                .take_while(|event| event.cycle <= session.cycles())
+                .count();
+            let end = (next + due).max(applied.load(Relaxed));
+            for event in &script[next..end] {
+                if event.pressed {
+                    session.press_key(event.key);
+                } else {
+                    session.release_key(event.key);
+                }
+            }
+            next = end;
+            applied.store(end, Relaxed);
+        }
+    })
+}
+
+/* Fuzzer input: a byte of quirks (bit 0 clips sprites, bit 1 enables SCHIP), the number of key
+ * events, two bytes per event (instructions since the previous one, then the key in the low
+ * nibble and bit 4 set for a press), and the ROM in the remaining bytes.
+ */
+pub fn run_bytes(data: &[u8]) {
+    let (quirks, count, rest) = match data {
+        [quirks, count, rest @ ..] => (*quirks, *count as usize, rest),
+        _ => return,
+    };
+    let (events, program) = rest.split_at((count * 2).min(rest.len()));
+
+    let mut cycle = 0;
+    let script: Vec<KeyEvent> = events
+        .chunks_exact(2)
+        .map(|event| {
+            cycle += event[0] as u64;
+            KeyEvent {
+                cycle,
+                key: event[1] & 0xF,
+                pressed: event[1] & 0x10 != 0,
+            }
+        })
+        .collect();
+
+    let config = Config {
+        clip_sprites: quirks & 1 != 0,
+        schip_compatibility: quirks & 2 != 0,
+        ..Default::default()
+    };
+    run(program, &script, &config);
+}
@@ -0,0 +1,254 @@
+use crate::args::{self, Arg, Args};
+use crate::emulator::DEFAULT_CLOCK_SPEED;
+use crate::error::{self, Error};
+use crate::headless;
+use crate::rom_file::{self, RomFile};
+use crate::ProgramOptions;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_MANIFEST: &str = "manifest.txt";
+const DEFAULT_MAX_CYCLES: u64 = 100_000;
+
+/* Expected-hash manifest, one ROM per line:
+ *     <rom file name> <framebuffer hash, hex> [-K] [-S]
+ * the optional flags select the quirks the ROM is run with; empty lines and lines starting
+ * with '#' are ignored
+ */
+struct ManifestEntry {
+    rom: String,
+    hash: u64,
+    clip_sprites: bool,
+    schip_compatibility: bool,
+}
+
+enum Verdict {
+    Pass,
+    Fail,
+    Missing,        // listed in the manifest, but not found in the directory
+    New,            // found in the directory, but not listed in the manifest
+    Invalid(Error), // can't be run, e.g. too large
+}
+
+struct TestOptions {
+    dir: PathBuf,
+    manifest: PathBuf,
+    max_cycles: u64,
+    clock_speed: u32,
+}
+
+fn parse_args(args: &[String]) -> Result<TestOptions, Error> {
+    let mut dir = None;
+    let mut manifest = None;
+    let mut max_cycles = DEFAULT_MAX_CYCLES;
+    let mut clock_speed = DEFAULT_CLOCK_SPEED;
+
+    let mut args = Args::new(args);
+    while let Some(arg) = args.next_arg() {
+        let (flag, inline) = match arg {
+            Arg::Positional(path) => {
+                if dir.replace(PathBuf::from(path)).is_some() {
+                    return Err(Error::InvalidArguments);
+                }
+                continue;
+            }
+            Arg::Flag(flag, inline) => (flag, inline),
+        };
+
+        match flag {
+            "--manifest" => manifest = Some(PathBuf::from(args.value(flag, inline)?)),
+            "--max-cycles" => {
+                let value = args.value(flag, inline)?;
+                max_cycles = args::parse_number(flag, value, 1..=u64::MAX, "a positive number")?;
+            }
+            "--clock" | "-C" => {
+                let value = args.value(flag, inline)?;
+                clock_speed = args::parse_number(flag, value, 1..=u32::MAX, "a positive number")?;
+            }
+            _ => return Err(Error::UnknownFlag(flag.to_owned())),
+        }
+    }
+
+    let dir = dir.ok_or(Error::InvalidArguments)?;
+    Ok(TestOptions {
+        manifest: manifest.unwrap_or_else(|| dir.join(DEFAULT_MANIFEST)),
+        dir,
+        max_cycles,
+        clock_speed,
+    })
+}
+
+/* `test <dir> [--manifest <file>] [--max-cycles N] [--clock N]` entry point,
+ * returns the process exit status */
+pub fn run(args: &[String]) -> i32 {
+    let TestOptions {
+        dir,
+        manifest,
+        max_cycles,
+        clock_speed,
+    } = parse_args(args).unwrap_or_else(|e| error::exit_with(e));
+
+    let entries = match read_manifest(&manifest) {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("ERROR: cannot read manifest {}: {}", manifest.display(), e);
+            return 1;
+        }
+    };
+
+    let roms = match list_roms(&dir, &manifest) {
+        Ok(roms) => roms,
+        Err(e) => {
+            println!("ERROR: cannot read directory {}: {}", dir.display(), e);
+            return 1;
+        }
+    };
+
+    println!("{:<32} {:<16} RESULT", "ROM", "HASH");
+
+    let mut failures = 0;
+    for entry in &entries {
+        let (hash, verdict) = match load_rom(&dir.join(&entry.rom)) {
+            Ok(program) => {
+                let hash = run_rom(program, entry, clock_speed, max_cycles);
+                (
+                    Some(hash),
+                    if hash == entry.hash {
+                        Verdict::Pass
+                    } else {
+                        Verdict::Fail
+                    },
+                )
+            }
+            Err(Error::RomNotFound(_)) => (None, Verdict::Missing),
+            Err(e) => (None, Verdict::Invalid(e)),
+        };
+
+        if !matches!(verdict, Verdict::Pass) {
+            failures += 1;
+        }
+        print_row(&entry.rom, hash, verdict);
+    }
+
+    // report unlisted ROMs with their current hash, so they can be added to the manifest
+    for rom in roms {
+        if entries.iter().any(|entry| entry.rom == rom) {
+            continue;
+        }
+
+        let entry = ManifestEntry {
+            rom,
+            hash: 0,
+            clip_sprites: false,
+            schip_compatibility: false,
+        };
+
+        match load_rom(&dir.join(&entry.rom)) {
+            Ok(program) => {
+                let hash = run_rom(program, &entry, clock_speed, max_cycles);
+                print_row(&entry.rom, Some(hash), Verdict::New);
+            }
+            Err(e) => print_row(&entry.rom, None, Verdict::Invalid(e)),
+        }
+    }
+
+    println!("{} of {} passed", entries.len() - failures, entries.len());
+
+    if failures == 0 {
+        0
+    } else {
+        1
+    }
+}
+
+/* what the desktop frontend would refuse to run is reported, rather than panicking the core */
+fn load_rom(path: &Path) -> Result<Vec<u8>, Error> {
+    let rom = RomFile::load(path, None)?;
+    rom.check_size(path)?;
+    Ok(rom.program)
+}
+
+fn run_rom(program: Vec<u8>, entry: &ManifestEntry, clock_speed: u32, max_cycles: u64) -> u64 {
+    let options = ProgramOptions {
+        schip_compatibility: entry.schip_compatibility,
+        clip_sprites: entry.clip_sprites,
+        clock_speed,
+        max_cycles,
+        program,
+        ..Default::default()
+    };
+
+    headless::framebuffer_hash(&headless::execute(&options).fb)
+}
+
+fn print_row(rom: &str, hash: Option<u64>, verdict: Verdict) {
+    let hash = hash.map_or(String::from("-"), |hash| format!("{:016x}", hash));
+    let verdict = match verdict {
+        Verdict::Pass => "pass".to_owned(),
+        Verdict::Fail => "FAIL".to_owned(),
+        Verdict::Missing => "MISSING".to_owned(),
+        Verdict::New => "new".to_owned(),
+        Verdict::Invalid(e) => format!("INVALID: {}", e),
+    };
+
+    println!("{:<32} {:<16} {}", rom, hash, verdict);
+}
+
+fn read_manifest(path: &Path) -> std::io::Result<Vec<ManifestEntry>> {
+    use std::io::{Error, ErrorKind};
+
+    let mut res = vec![];
+
+    for (n, line) in std::fs::read_to_string(path)?.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid_line = || Error::new(ErrorKind::InvalidData, format!("line {}", n + 1));
+
+        let mut fields = line.split_whitespace();
+        let rom = fields.next().ok_or_else(invalid_line)?;
+        let hash = fields.next().ok_or_else(invalid_line)?;
+        let hash = u64::from_str_radix(hash, 16).map_err(|_| invalid_line())?;
+
+        let mut entry = ManifestEntry {
+            rom: String::from(rom),
+            hash,
+            clip_sprites: false,
+            schip_compatibility: false,
+        };
+
+        for flag in fields {
+            match flag {
+                "-K" => entry.clip_sprites = true,
+                "-S" => entry.schip_compatibility = true,
+                _ => return Err(invalid_line()),
+            }
+        }
+
+        res.push(entry);
+    }
+
+    Ok(res)
+}
+
+fn list_roms(dir: &Path, manifest: &Path) -> std::io::Result<Vec<String>> {
+    let mut res = vec![];
+
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.is_file() || path == manifest {
+            continue;
+        }
+
+        // READMEs, settings and archives sit next to the ROMs
+        if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+            if rom_file::has_extension(name, &rom_file::ROM_EXTENSIONS) {
+                res.push(String::from(name));
+            }
+        }
+    }
+
+    res.sort();
+    Ok(res)
+}
@@ -0,0 +1,296 @@
+use crate::emulator::{DEFAULT_CLOCK_SPEED, UNCAPPED};
+use crate::screen::is_pixel_on;
+use crate::ProgramOptions;
+use chip_8_core::FrameBuffer;
+use chip_8_desktop::session::{self, Config, Session};
+
+pub const EXIT_FINISHED: i32 = 0;
+pub const EXIT_DIVERGED: i32 = 1;
+pub const EXIT_WAITING_FOR_KEY: i32 = 2;
+pub const EXIT_FAULT: i32 = 3;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Outcome {
+    pub fb: FrameBuffer,
+    pub cycles: u64,
+    pub waiting_for_key: bool,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub faulted: bool, // the core panicked, see `Session::try_step`
+}
+
+/* the observable machine state, the core doesn't expose its registers */
+pub fn outcome(session: &Session) -> Outcome {
+    Outcome {
+        fb: *session.framebuffer(),
+        cycles: session.cycles(),
+        waiting_for_key: session.is_waiting_for_key(),
+        delay_timer: session.delay_timer(),
+        sound_timer: session.sound_timer(),
+        faulted: session.fault().is_some(),
+    }
+}
+
+pub fn session_config(options: &ProgramOptions) -> Config {
+    Config {
+        clip_sprites: options.clip_sprites,
+        schip_compatibility: options.schip_compatibility,
+        // emulated time has no rate of its own when uncapped
+        clock_speed: match options.clock_speed {
+            UNCAPPED => DEFAULT_CLOCK_SPEED,
+            clock_speed => clock_speed,
+        },
+        ..Default::default()
+    }
+}
+
+/* no input source is attached, a ROM waiting for a key can't make any further progress */
+pub fn with_stepper<R>(options: &ProgramOptions, f: impl FnOnce(&mut Session) -> R) -> R {
+    session::with_session(&options.program, &session_config(options), f)
+}
+
+/* run for at most `options.max_cycles` instructions, stopping early if the ROM waits for a key
+ * or the core fails */
+pub fn execute(options: &ProgramOptions) -> Outcome {
+    with_stepper(options, |stepper| {
+        while stepper.cycles() < options.max_cycles && !stepper.is_waiting_for_key() {
+            if let Err(fault) = stepper.try_step() {
+                tracing::error!("{}", fault);
+                break;
+            }
+        }
+
+        outcome(stepper)
+    })
+}
+
+/* `--headless` entry point, returns the process exit status */
+pub fn run(options: &ProgramOptions) -> i32 {
+    let outcome = execute(options);
+
+    if let Some(path) = &options.fb_out {
+        if let Err(e) = write_framebuffer(path, &outcome.fb) {
+            crate::error::report(&format!("Cannot write framebuffer to {}: {}", path, e));
+        }
+    }
+
+    // the core doesn't expose its registers, the timers are the only observable machine state
+    print!("{}", framebuffer_to_text(&outcome.fb));
+    println!("cycles: {}", outcome.cycles);
+    println!("delay timer: {}", outcome.delay_timer);
+    println!("sound timer: {}", outcome.sound_timer);
+
+    if outcome.faulted {
+        println!("halted: core error");
+        EXIT_FAULT
+    } else if outcome.waiting_for_key {
+        println!("halted: waiting for key");
+        EXIT_WAITING_FOR_KEY
+    } else {
+        EXIT_FINISHED
+    }
+}
+
+pub fn framebuffer_to_text(fb: &FrameBuffer) -> String {
+    let mut res =
+        String::with_capacity((chip_8_core::SCREEN_WIDTH + 1) * chip_8_core::SCREEN_HEIGHT);
+
+    for y in 0..chip_8_core::SCREEN_HEIGHT {
+        for x in 0..chip_8_core::SCREEN_WIDTH {
+            res.push(if is_pixel_on(fb, x, y) { '#' } else { '.' });
+        }
+        res.push('\n');
+    }
+
+    res
+}
+
+/* Binary PBM, whose rows are packed the same way as the framebuffer's (most significant bit
+ * first, 1 for a pixel that is on), or the bare framebuffer bytes for any other extension */
+pub fn write_framebuffer(path: &str, fb: &FrameBuffer) -> std::io::Result<()> {
+    let mut data = Vec::with_capacity(16 + fb.len());
+    if path.ends_with(".pbm") {
+        let header = format!(
+            "P4\n{} {}\n",
+            chip_8_core::SCREEN_WIDTH,
+            chip_8_core::SCREEN_HEIGHT
+        );
+        data.extend_from_slice(header.as_bytes());
+    }
+    data.extend_from_slice(fb);
+
+    std::fs::write(path, data)
+}
+
+pub fn framebuffer_hash(fb: &FrameBuffer) -> u64 {
+    fnv1a(fb)
+}
+
+/* FNV-1a, stable across platforms and toolchains (unlike std's DefaultHasher), so that the
+ * hashes can be stored in manifests */
+pub fn fnv1a(data: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    data.iter().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(PRIME)
+    })
+}
+
+/* `--quirk-ab` entry point: run the ROM under every quirk permutation and report which of the
+ * quirk flags actually change its behaviour, returns the process exit status */
+pub fn run_quirk_ab(options: &ProgramOptions) -> i32 {
+    const PERMUTATIONS: [(bool, bool); 4] =
+        [(false, false), (true, false), (false, true), (true, true)];
+
+    let outcomes: Vec<(u64, bool)> = PERMUTATIONS
+        .iter()
+        .map(|&(clip_sprites, schip_compatibility)| {
+            let options = ProgramOptions {
+                clip_sprites,
+                schip_compatibility,
+                clock_speed: options.clock_speed,
+                max_cycles: options.max_cycles,
+                program: options.program.clone(),
+                ..Default::default()
+            };
+            let outcome = execute(&options);
+            (framebuffer_hash(&outcome.fb), outcome.waiting_for_key)
+        })
+        .collect();
+
+    println!(
+        "{:<14} {:<14} {:<16} HALTED",
+        "CLIP SPRITES", "SCHIP OPCODES", "HASH"
+    );
+    for (&(clip_sprites, schip_compatibility), &(hash, waiting)) in
+        PERMUTATIONS.iter().zip(outcomes.iter())
+    {
+        println!(
+            "{:<14} {:<14} {:016x} {}",
+            clip_sprites,
+            schip_compatibility,
+            hash,
+            if waiting { "waiting for key" } else { "-" }
+        );
+    }
+
+    // a flag matters if toggling it alone changes the outcome, for any value of the other flag
+    let clip_matters = outcomes[0] != outcomes[1] || outcomes[2] != outcomes[3];
+    let schip_matters = outcomes[0] != outcomes[2] || outcomes[1] != outcomes[3];
+
+    let verdict = |matters| {
+        if matters {
+            "affects this ROM"
+        } else {
+            "no effect"
+        }
+    };
+    println!("--clip-sprites:  {}", verdict(clip_matters));
+    println!("--schip-opcodes: {}", verdict(schip_matters));
+
+    EXIT_FINISHED
+}
+
+/* `--lockstep` entry point: run the ROM under two quirk configurations side by side, comparing
+ * the observable machine state after every instruction, returns the process exit status */
+pub fn run_lockstep(options: &ProgramOptions, other_quirks: (bool, bool)) -> i32 {
+    let (clip_sprites, schip_compatibility) = other_quirks;
+    let other = ProgramOptions {
+        clip_sprites,
+        schip_compatibility,
+        clock_speed: options.clock_speed,
+        max_cycles: options.max_cycles,
+        program: options.program.clone(),
+        ..Default::default()
+    };
+
+    // `--verify`
+    if other_quirks == (options.clip_sprites, options.schip_compatibility) {
+        println!("same quirks on both runs: checking determinism, there is no second backend");
+    }
+
+    with_stepper(options, |a| {
+        with_stepper(&other, |b| {
+            while a.cycles() < options.max_cycles {
+                if a.is_waiting_for_key() || b.is_waiting_for_key() {
+                    break;
+                }
+
+                // a fault on one side only shows up as a divergence
+                let results = [a.try_step(), b.try_step()];
+
+                let (outcome_a, outcome_b) = (outcome(a), outcome(b));
+                if outcome_a != outcome_b {
+                    report_divergence(&outcome_a, &outcome_b);
+                    report_faults(a, b);
+                    return EXIT_DIVERGED;
+                }
+                // the same instruction failed on both sides, there's nothing left to run
+                if results.iter().any(Result::is_err) {
+                    println!("no divergence, halted at cycle {}", a.cycles());
+                    report_faults(a, b);
+                    return EXIT_FAULT;
+                }
+            }
+
+            println!("no divergence after {} cycles", a.cycles());
+            EXIT_FINISHED
+        })
+    })
+}
+
+fn report_divergence(a: &Outcome, b: &Outcome) {
+    println!("diverged at cycle {}", a.cycles);
+
+    if a.fb != b.fb {
+        println!("framebuffer:");
+        let (text_a, text_b) = (framebuffer_to_text(&a.fb), framebuffer_to_text(&b.fb));
+        for (row_a, row_b) in text_a.lines().zip(text_b.lines()) {
+            println!(
+                "{}  {}{}",
+                row_a,
+                row_b,
+                if row_a != row_b { " <" } else { "" }
+            );
+        }
+    }
+    if a.delay_timer != b.delay_timer {
+        println!("delay timer: {} vs {}", a.delay_timer, b.delay_timer);
+    }
+    if a.sound_timer != b.sound_timer {
+        println!("sound timer: {} vs {}", a.sound_timer, b.sound_timer);
+    }
+    if a.waiting_for_key != b.waiting_for_key {
+        println!(
+            "waiting for key: {} vs {}",
+            a.waiting_for_key, b.waiting_for_key
+        );
+    }
+}
+
+fn report_faults(a: &Session, b: &Session) {
+    for (side, session) in [("first", a), ("second", b)] {
+        if let Some(fault) = session.fault() {
+            println!("{} run: {}", side, fault);
+        }
+    }
+}
+
+/* parse a quirk set, e.g. "KS", "K", or "none" (same letters as the short quirk flags) */
+pub fn parse_quirks(arg: &str) -> Option<(bool, bool)> {
+    if arg == "none" {
+        return Some((false, false));
+    }
+
+    let mut res = (false, false);
+    for c in arg.chars() {
+        match c {
+            'K' => res.0 = true,
+            'S' => res.1 = true,
+            _ => return None,
+        }
+    }
+
+    Some(res)
+}
@@ -0,0 +1,168 @@
+use crate::cartridge::Cartridge;
+use crate::error::MAX_ROM_SIZE;
+use crate::rom_file::RomFile;
+use crate::variant::{self, PROGRAM_START};
+use sha1::{Digest, Sha1};
+use std::path::Path;
+
+/* shortest run of printable characters reported as a string */
+const MIN_STRING_LEN: usize = 4;
+
+/* `info <rom> [--entry <name>]` entry point: what can be told about a ROM without running it,
+ * returns the process exit status */
+pub fn run(args: &[String]) -> i32 {
+    let (path, rom) = match load_rom("info", args) {
+        Some(res) => res,
+        None => return 1,
+    };
+    let program = &rom.program;
+
+    println!("ROM:      {}", rom.stem);
+    println!(
+        "Size:     {} bytes{}",
+        program.len(),
+        match MAX_ROM_SIZE.checked_sub(program.len()) {
+            Some(free) => format!(", {} free", free),
+            None => ", TOO LARGE to fit in memory".to_owned(),
+        }
+    );
+    // what ROM databases identify programs by
+    println!("SHA-1:    {:x}", Sha1::digest(program));
+    // the hash of the test manifests and of the play history
+    println!("FNV-1a:   {:016x}", crate::headless::fnv1a(program));
+
+    // settings shipped with the ROM are all the metadata there is
+    let cartridge = match &rom.sidecar {
+        Some((name, text)) => Cartridge::parse(&Path::new(path).join(name), text),
+        None => Cartridge::load(Path::new(path)).map(Option::unwrap_or_default),
+    };
+    match cartridge {
+        Ok(cartridge) => {
+            if let Some(title) = cartridge.title {
+                println!("Title:    {}", title);
+            }
+            if !cartridge.authors.is_empty() {
+                println!("Authors:  {}", cartridge.authors.join(", "));
+            }
+        }
+        Err(e) => println!("Settings: {}", e),
+    }
+
+    match variant::detect(program) {
+        (variant, Some((address, opcode))) => println!(
+            "Variant:  {} (opcode {:04X} at {:#05X})",
+            variant.name(),
+            opcode,
+            address
+        ),
+        (variant, None) => println!("Variant:  {}", variant.name()),
+    }
+
+    // what isn't reachable as code is taken for data: sprites, tables, text
+    let instructions = variant::reachable(program);
+    let mut is_code = vec![false; program.len()];
+    for (address, opcode) in &instructions {
+        // XO-CHIP long index load, followed by a 16-bit address
+        let len = if *opcode == 0xF000 { 4 } else { 2 };
+        let offset = address - PROGRAM_START;
+        for byte in is_code.iter_mut().skip(offset).take(len) {
+            *byte = true;
+        }
+    }
+    let code_bytes = is_code.iter().filter(|code| **code).count();
+    println!(
+        "Code:     {} instructions reachable from the entry point, {} bytes of data",
+        instructions.len(),
+        program.len() - code_bytes
+    );
+
+    let mut histogram: Vec<(&str, usize)> = Vec::new();
+    for (_, opcode) in &instructions {
+        let group = group(*opcode);
+        match histogram.iter_mut().find(|(name, _)| *name == group) {
+            Some((_, count)) => *count += 1,
+            None => histogram.push((group, 1)),
+        }
+    }
+    histogram.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    for (group, count) in histogram {
+        println!("          {:>5}  {}", count, group);
+    }
+
+    for (offset, text) in strings(program, &is_code) {
+        println!("String:   {:#05X} \"{}\"", offset + PROGRAM_START, text);
+    }
+
+    0
+}
+
+/* `<rom> [--entry <name>]` arguments of the ROM tools, errors are printed */
+pub fn load_rom<'a>(command: &str, args: &'a [String]) -> Option<(&'a String, RomFile)> {
+    let (path, entry) = match args {
+        [path] => (path, None),
+        [path, flag, entry] | [flag, entry, path] if flag == "--entry" => (path, Some(entry)),
+        _ => {
+            println!("ERROR: Invalid arguments!");
+            println!("usage: {} <rom> [--entry <name>]", command);
+            return None;
+        }
+    };
+
+    match RomFile::load(Path::new(path), entry.map(String::as_str)) {
+        Ok(rom) => Some((path, rom)),
+        Err(e) => {
+            println!("ERROR: {}", e);
+            None
+        }
+    }
+}
+
+/* rough instruction classes, in Octo's terms */
+fn group(opcode: u16) -> &'static str {
+    match (opcode >> 12, opcode & 0xFF) {
+        (0x0, 0xE0) => "clear",
+        (0x0, 0xEE) => "return",
+        (0x0, _) => "scroll, resolution, exit, machine code",
+        (0x1, _) => "jump",
+        (0x2, _) => "call",
+        (0x3..=0x5 | 0x9, _) => "skip on registers",
+        (0x6, _) => "load byte",
+        (0x7, _) => "add byte",
+        (0x8, _) => "register arithmetic",
+        (0xA, _) => "load index",
+        (0xB, _) => "computed jump",
+        (0xC, _) => "random",
+        (0xD, _) => "sprite",
+        (0xE, _) => "skip on keys",
+        (0xF, 0x07 | 0x15 | 0x18) => "timers",
+        (0xF, 0x0A) => "wait for key",
+        (0xF, 0x55 | 0x65 | 0x75 | 0x85) => "save, load",
+        (0xF, _) => "index, font, BCD",
+        _ => unreachable!(),
+    }
+}
+
+/* runs of printable ASCII in the data */
+fn strings(program: &[u8], is_code: &[bool]) -> Vec<(usize, String)> {
+    let mut res = Vec::new();
+    let mut start = None;
+
+    for (offset, byte) in program.iter().enumerate() {
+        let printable = !is_code[offset] && (byte.is_ascii_graphic() || *byte == b' ');
+        match (printable, start) {
+            (true, None) => start = Some(offset),
+            (false, Some(from)) => {
+                if offset - from >= MIN_STRING_LEN {
+                    res.push((from, String::from_utf8_lossy(&program[from..offset]).into()));
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(from) = start.filter(|from| program.len() - from >= MIN_STRING_LEN) {
+        res.push((from, String::from_utf8_lossy(&program[from..]).into()));
+    }
+
+    res
+}
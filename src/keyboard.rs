@@ -1,16 +1,145 @@
-use std::sync::mpsc::Receiver;
+use crate::error::{self, Error, ErrorSlot};
+use std::sync::atomic::AtomicU16;
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
 use std::sync::Arc;
 use std::sync::{Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/* fastest autofire: a key toggling faster than the 60 Hz timers would be missed by most games */
+pub const MAX_TURBO_RATE: u8 = 30;
 
 pub type KeyValue = u8;
+#[derive(Clone, Copy, Debug)]
 pub enum KeyAction {
     Pressed,
     Released,
 }
 
-/* keyboard events received from the emulator */
+/* keyboard events received from the emulator, or reported by an input source */
 pub type KeyMessage = (KeyValue, KeyAction);
 
+/* names accepted by `--disable-input` */
+pub const INPUT_SOURCES: [&str; 4] = ["keyboard", "mouse", "network", "chat"];
+
+/* Anything pressing keypad keys: the host keyboard and mouse, spectators, the chat. Sources only
+ * report their own events, merging them is up to `Inputs`.
+ */
+pub trait InputSource: Send {
+    /* one of INPUT_SOURCES */
+    fn name(&self) -> &'static str;
+
+    /* events since the last call, polled on every frame */
+    fn poll(&mut self) -> Vec<KeyMessage>;
+}
+
+/* events pushed by the window event handlers */
+pub struct HostInput {
+    name: &'static str,
+    rx: Receiver<KeyMessage>,
+}
+
+impl HostInput {
+    pub fn new(name: &'static str) -> (Self, Sender<KeyMessage>) {
+        let (tx, rx) = mpsc::channel();
+        (Self { name, rx }, tx)
+    }
+}
+
+impl InputSource for HostInput {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn poll(&mut self) -> Vec<KeyMessage> {
+        self.rx.try_iter().collect()
+    }
+}
+
+struct Source {
+    input: Box<dyn InputSource>,
+    enabled: bool,
+    held: [u8; 16], // a key can be held more than once, e.g. through two host keys bound to it
+}
+
+/* every input source, merged into a single keypad: a key is down while any source holds it */
+#[derive(Default)]
+pub struct Inputs {
+    sources: Vec<Source>,
+    down: [bool; 16], // as last reported by `poll`
+}
+
+impl Inputs {
+    pub fn add(&mut self, input: impl InputSource + 'static) {
+        self.sources.push(Source {
+            input: Box::new(input),
+            enabled: true,
+            held: [0; 16],
+        });
+    }
+
+    /* a disabled source is still polled, its events are dropped; what it held is released */
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) {
+        for source in self.sources.iter_mut().filter(|s| s.input.name() == name) {
+            tracing::info!(source = name, enabled, "input source toggled");
+            source.enabled = enabled;
+            source.held = [0; 16];
+        }
+    }
+
+    /* keys held through the sources called `name` */
+    pub fn held_by(&self, name: &str) -> [bool; 16] {
+        let mut res = [false; 16];
+        for source in self.sources.iter().filter(|s| s.input.name() == name) {
+            for (key, held) in source.held.iter().enumerate() {
+                res[key] |= *held > 0;
+            }
+        }
+        res
+    }
+
+    /* changes of the merged keypad since the last call */
+    pub fn poll(&mut self) -> Vec<KeyMessage> {
+        for source in &mut self.sources {
+            let events = source.input.poll();
+            if !source.enabled {
+                continue;
+            }
+            for (key, action) in events {
+                let held = &mut source.held[key as usize];
+                *held = match action {
+                    KeyAction::Pressed => held.saturating_add(1),
+                    KeyAction::Released => held.saturating_sub(1),
+                };
+            }
+        }
+
+        let mut res = Vec::new();
+        for key in 0..16 {
+            let down = self.sources.iter().any(|s| s.held[key] > 0);
+            if down != self.down[key] {
+                self.down[key] = down;
+                let action = if down {
+                    KeyAction::Pressed
+                } else {
+                    KeyAction::Released
+                };
+                res.push((key as KeyValue, action));
+            }
+        }
+        res
+    }
+
+    /* the machine was rebuilt, and knows of no held keys */
+    pub fn reset(&mut self) {
+        for source in &mut self.sources {
+            source.held = [0; 16];
+        }
+        self.down = [false; 16];
+    }
+}
+
 /* state of the keyboard thread
  * used to send messages to the emulator, and to manage the keyboard state machine */
 #[derive(Clone, Copy, Default, PartialEq, Eq)]
@@ -18,74 +147,410 @@ pub enum KeyboardState {
     #[default]
     Normal,
     Waiting,
+    HeldWhileWaiting(KeyValue), // waiting for release, the key completes the wait once released
     PressedWhileWaiting(KeyValue),
+    Cancelled,    // the wait was abandoned (e.g. for a reset), the core gets a dummy key
+    ShuttingDown, // the keyboard thread has exited, nobody is left to wake up waiting callers
 }
 
+pub type KeyboardSyncPair = (Condvar, Mutex<KeyboardState>);
+
+/* Key events go through `handle`, called either by the keyboard thread with the events sent to
+ * it (see `spawn`) or, with `--direct-input`, straight from the event handlers of the main thread:
+ * Ex9E/ExA1 then see a key without waiting for a thread hop, and autofire is driven by the frame
+ * loop (`autofire`). Either way a single thread writes the held keys.
+ */
 pub struct KeyboardManager {
-    pressed_keys: Mutex<[bool; 16]>,
+    // one bit per key
+    pressed_keys: AtomicU16,
 
     // initialized as None, after any assignment can only be set as None again by wait_for_key()
     last_key: Mutex<Option<KeyValue>>,
-}
 
-impl Default for KeyboardManager {
-    fn default() -> Self {
-        Self {
-            pressed_keys: Mutex::new([false; 16]),
-            last_key: Mutex::new(None),
-        }
-    }
+    // keys checked by the program (Ex9E, ExA1) since the last `take_polled`, one bit per key
+    polled: AtomicU16,
+
+    // autofire keys: while held they alternate between pressed and released, every half period
+    turbo: [Option<Duration>; 16],
+
+    // next toggle of each held autofire key
+    toggles: Mutex<[Option<Instant>; 16]>,
+
+    // Fx0A completes when the key is released, as on the COSMAC VIP, rather than when pressed
+    wait_for_release: bool,
+
+    errors: Arc<ErrorSlot>,
 }
 
 impl KeyboardManager {
-    pub fn new(rx_in: Receiver<KeyMessage>) -> (Arc<Self>, Arc<(Condvar, Mutex<KeyboardState>)>) {
-        let km = KeyboardManager::default();
-        let res = Arc::new(km);
+    /* `turbo` are autofire rates in Hz */
+    pub fn new(
+        turbo: &[(KeyValue, u8)],
+        wait_for_release: bool,
+        errors: Arc<ErrorSlot>,
+    ) -> (Arc<Self>, Arc<KeyboardSyncPair>) {
+        let mut half_periods = [None; 16];
+        for (key, rate) in turbo {
+            half_periods[*key as usize] = Some(Duration::from_secs(1) / (2 * *rate as u32));
+        }
 
+        let km = KeyboardManager {
+            pressed_keys: AtomicU16::new(0),
+            last_key: Mutex::new(None),
+            polled: AtomicU16::new(0),
+            turbo: half_periods,
+            toggles: Mutex::new([None; 16]),
+            wait_for_release,
+            errors,
+        };
         let sync_pair = Arc::new((Condvar::new(), Mutex::new(KeyboardState::default())));
 
-        let r1 = Arc::clone(&res);
-        let s1 = Arc::clone(&sync_pair);
+        (Arc::new(km), sync_pair)
+    }
+
+    /* the keyboard thread, handling the events of `rx_in` until the sender is dropped */
+    pub fn spawn(
+        self: &Arc<Self>,
+        rx_in: Receiver<KeyMessage>,
+        sync_pair: &Arc<KeyboardSyncPair>,
+    ) -> JoinHandle<()> {
+        let r1 = Arc::clone(self);
+        let s1 = Arc::clone(sync_pair);
 
-        std::thread::spawn(move || r1.start(rx_in, s1));
+        std::thread::spawn(move || {
+            let _span = tracing::info_span!("keyboard").entered();
 
-        (res, sync_pair)
+            if let Err(e) = error::catch_panic("keyboard", || r1.start(rx_in, &s1)) {
+                r1.errors.raise(e);
+            }
+            Self::stop(&s1);
+        })
+    }
+
+    /* nobody is left to complete a wait for a key: wake up the waiting callers */
+    pub fn stop(sync_pair: &KeyboardSyncPair) {
+        let (cvar, mtx) = sync_pair;
+        // a poisoned state can't be waited on anyway
+        if let Ok(mut state) = mtx.lock() {
+            *state = KeyboardState::ShuttingDown;
+        }
+        cvar.notify_all();
     }
 
-    fn start(&self, rx_in: Receiver<KeyMessage>, sync_pair: Arc<(Condvar, Mutex<KeyboardState>)>) {
+    fn start(
+        &self,
+        rx_in: Receiver<KeyMessage>,
+        sync_pair: &KeyboardSyncPair,
+    ) -> Result<(), Error> {
         /* keyboard thread loop */
         loop {
-            let (key, action) = rx_in.recv().unwrap();
-            let (cvar, mtx) = sync_pair.as_ref();
+            let next_toggle = self
+                .toggles
+                .lock()
+                .map_err(Error::sync("autofire"))?
+                .iter()
+                .flatten()
+                .min()
+                .copied();
 
-            match action {
-                KeyAction::Pressed => {
-                    {
-                        **(self.last_key.lock().as_mut().unwrap()) = Some(key);
+            // the sender is dropped together with the emulator, nothing left to do
+            let received = match next_toggle {
+                Some(deadline) => {
+                    match rx_in.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                        Ok(message) => Some(message),
+                        Err(RecvTimeoutError::Timeout) => None,
+                        Err(RecvTimeoutError::Disconnected) => break,
                     }
-                    {
-                        self.pressed_keys.lock().unwrap()[key as usize] = true;
-                    }
-                    {
-                        let mut state = mtx.lock().unwrap();
-                        match *state {
-                            KeyboardState::Normal => continue,
-                            KeyboardState::PressedWhileWaiting(_) => continue,
-                            KeyboardState::Waiting => {
-                                *state = KeyboardState::PressedWhileWaiting(key)
-                            }
-                        }
-                    }
-                    cvar.notify_all();
                 }
-                KeyAction::Released => {
-                    self.pressed_keys.lock().unwrap()[key as usize] = false;
+                None => match rx_in.recv() {
+                    Ok(message) => Some(message),
+                    Err(_) => break,
+                },
+            };
+
+            match received {
+                Some(message) => self.handle(message, sync_pair)?,
+                None => self.autofire(sync_pair)?,
+            }
+        }
+
+        tracing::debug!("keyboard thread stopped");
+        Ok(())
+    }
+
+    /* an event of the merged keypad */
+    pub fn handle(
+        &self,
+        (key, action): KeyMessage,
+        sync_pair: &KeyboardSyncPair,
+    ) -> Result<(), Error> {
+        tracing::trace!(key, ?action, "key event");
+
+        let toggle = match action {
+            KeyAction::Pressed => self.turbo[key as usize].map(|t| Instant::now() + t),
+            KeyAction::Released => None,
+        };
+        self.toggles.lock().map_err(Error::sync("autofire"))?[key as usize] = toggle;
+
+        match action {
+            KeyAction::Pressed => {
+                self.set_pressed(key, true);
+                self.press(key, sync_pair)
+            }
+            KeyAction::Released => {
+                self.set_pressed(key, false);
+                self.release(key, sync_pair)
+            }
+        }
+    }
+
+    fn set_pressed(&self, key: KeyValue, pressed: bool) {
+        if pressed {
+            self.pressed_keys.fetch_or(1 << key, Relaxed);
+        } else {
+            self.pressed_keys.fetch_and(!(1 << key), Relaxed);
+        }
+    }
+
+    /* the wait state machine, the key itself is already marked as pressed */
+    fn press(&self, key: KeyValue, sync_pair: &KeyboardSyncPair) -> Result<(), Error> {
+        let (cvar, mtx) = sync_pair;
+        {
+            *self
+                .last_key
+                .lock()
+                .map_err(Error::sync("keyboard state"))? = Some(key);
+        }
+        {
+            let mut state = mtx.lock().map_err(Error::sync("keyboard state"))?;
+            match *state {
+                KeyboardState::Normal => return Ok(()),
+                KeyboardState::HeldWhileWaiting(_) => return Ok(()),
+                KeyboardState::PressedWhileWaiting(_) => return Ok(()),
+                KeyboardState::Cancelled => return Ok(()),
+                KeyboardState::ShuttingDown => return Ok(()),
+                KeyboardState::Waiting if self.wait_for_release => {
+                    *state = KeyboardState::HeldWhileWaiting(key);
+                    return Ok(());
+                }
+                KeyboardState::Waiting => *state = KeyboardState::PressedWhileWaiting(key),
+            }
+        }
+        cvar.notify_all();
+
+        Ok(())
+    }
+
+    fn release(&self, key: KeyValue, sync_pair: &KeyboardSyncPair) -> Result<(), Error> {
+        let (cvar, mtx) = sync_pair;
+        {
+            let mut state = mtx.lock().map_err(Error::sync("keyboard state"))?;
+            match *state {
+                KeyboardState::HeldWhileWaiting(held) if held == key => {
+                    *state = KeyboardState::PressedWhileWaiting(key)
                 }
+                _ => return Ok(()),
             }
         }
+        cvar.notify_all();
+
+        Ok(())
+    }
+
+    /* toggle the autofire keys that are due, each toggle to "pressed" counting as a new press; to
+     * be called by the thread handling the events */
+    pub fn autofire(&self, sync_pair: &KeyboardSyncPair) -> Result<(), Error> {
+        let now = Instant::now();
+        let mut toggles = self.toggles.lock().map_err(Error::sync("autofire"))?;
+
+        for (key, toggle) in toggles.iter_mut().enumerate() {
+            let (due, half_period) = match (*toggle, self.turbo[key]) {
+                (Some(due), Some(half_period)) if due <= now => (due, half_period),
+                _ => continue,
+            };
+            *toggle = Some(due + half_period);
+
+            let key = key as KeyValue;
+            if self.is_down(key) {
+                self.set_pressed(key, false);
+                self.release(key, sync_pair)?;
+            } else {
+                self.set_pressed(key, true);
+                self.press(key, sync_pair)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_down(&self, key: KeyValue) -> bool {
+        self.pressed_keys.load(Relaxed) & (1 << key) != 0
     }
 
     pub fn is_pressed(&self, key_code: u8) -> bool {
-        self.pressed_keys.lock().unwrap()[key_code as usize]
+        self.polled.fetch_or(1 << key_code, Relaxed);
+        self.is_down(key_code)
+    }
+
+    /* as seen by the program, for display */
+    pub fn pressed_keys(&self) -> [bool; 16] {
+        std::array::from_fn(|key| self.is_down(key as KeyValue))
     }
+
+    pub fn take_polled(&self) -> u16 {
+        self.polled.swap(0, Relaxed)
+    }
+}
+
+/* host keyboard layout (by scancode, so it doesn't depend on the OS keymap):
+ *   1 2 3 4        1 2 3 C
+ *   Q W E R   ->   4 5 6 D
+ *   A S D F        7 8 9 E
+ *   Z X C V        A 0 B F
+ */
+#[rustfmt::skip]
+pub fn scancode_to_key(scancode: u32) -> Option<KeyValue> {
+    match scancode {
+        0x2D => Some(0x0), 0x02 => Some(0x1), 0x03 => Some(0x2), 0x04 => Some(0x3),
+        0x10 => Some(0x4), 0x11 => Some(0x5), 0x12 => Some(0x6), 0x1E => Some(0x7),
+        0x1F => Some(0x8), 0x20 => Some(0x9), 0x2C => Some(0xA), 0x2E => Some(0xB),
+        0x05 => Some(0xC), 0x13 => Some(0xD), 0x21 => Some(0xE), 0x2F => Some(0xF),
+        _ => None,
+    }
+}
+
+/* host keys bound by the ROM settings take the place of the default layout */
+pub fn bound_key(bindings: &[(u32, KeyValue)], scancode: u32) -> Option<KeyValue> {
+    bindings
+        .iter()
+        .find(|(bound, _)| *bound == scancode)
+        .map(|(_, keycode)| *keycode)
+        .or_else(|| scancode_to_key(scancode))
+}
+
+/* rows of letters and digits on a US layout, by the scancode of their first key */
+const HOST_ROWS: [(&str, u32); 4] = [
+    ("1234567890", 0x02),
+    ("qwertyuiop", 0x10),
+    ("asdfghjkl", 0x1E),
+    ("zxcvbnm", 0x2C),
+];
+
+/* host key names accepted in key bindings, by position on a US layout like the mapping above;
+ * other keys are given by scancode, e.g. "0x56", as shown by `--key-diagnostics` */
+pub fn parse_host_key(name: &str) -> Option<u32> {
+    if let Some(hex) = name.strip_prefix("0x") {
+        return u32::from_str_radix(hex, 16).ok();
+    }
+
+    match name {
+        "space" => return Some(0x39),
+        "enter" => return Some(0x1C),
+        "tab" => return Some(0x0F),
+        _ => {}
+    }
+
+    let mut chars = name.chars();
+    let c = chars.next()?.to_ascii_lowercase();
+    if chars.next().is_some() {
+        return None;
+    }
+
+    HOST_ROWS
+        .iter()
+        .find_map(|(row, first)| row.find(c).map(|i| first + i as u32))
+}
+
+/* (row, column) of a host key among `HOST_ROWS`, None for the other keys */
+fn host_position(scancode: u32) -> Option<(usize, usize)> {
+    HOST_ROWS
+        .iter()
+        .enumerate()
+        .find_map(|(row, (keys, first))| {
+            let column = scancode.checked_sub(*first)? as usize;
+            (column < keys.len()).then_some((row, column))
+        })
+}
+
+/* the host key a keypad key is played with: bound by the ROM settings or `--keys`, else the one of
+ * the default layout, unless it was bound to another keypad key */
+fn host_key(bindings: &[(u32, KeyValue)], key: KeyValue) -> Option<u32> {
+    bindings
+        .iter()
+        .find(|(_, bound)| *bound == key)
+        .map(|(scancode, _)| *scancode)
+        .or_else(|| {
+            (0..0x80).find(|scancode| {
+                scancode_to_key(*scancode) == Some(key)
+                    && bound_key(bindings, *scancode) == Some(key)
+            })
+        })
+}
+
+/* Most keyboards are wired as a matrix of rows and columns, and can't tell which keys are held
+ * when three of them sit on the corners of a rectangle: the fourth corner either "ghosts" or,
+ * more commonly, the third key is silently dropped. Rows and columns of the host layout above are
+ * a good approximation of the wiring, so flag chords containing such a triple among the host keys
+ * the held keypad keys are bound to; keys outside of those rows are left out.
+ */
+pub fn is_ghosting_prone(held: &[bool; 16], bindings: &[(u32, KeyValue)]) -> bool {
+    let held: Vec<(usize, usize)> = (0..16)
+        .filter(|key| held[*key as usize])
+        .filter_map(|key| host_position(host_key(bindings, key)?))
+        .collect();
+
+    // a corner key shares its row with one of the others and its column with the other one
+    held.iter().any(|corner| {
+        held.iter().any(|same_row| {
+            same_row != corner
+                && same_row.0 == corner.0
+                && held
+                    .iter()
+                    .any(|same_col| same_col != corner && same_col.1 == corner.1)
+        })
+    })
+}
+
+/* mouse inputs that can be bound to keypad keys */
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MouseInput {
+    Left,
+    Right,
+    Middle,
+    WheelUp,
+    WheelDown,
+}
+
+impl MouseInput {
+    pub fn from_button(button: ggez::input::mouse::MouseButton) -> Option<Self> {
+        use ggez::input::mouse::MouseButton;
+
+        match button {
+            MouseButton::Left => Some(Self::Left),
+            MouseButton::Right => Some(Self::Right),
+            MouseButton::Middle => Some(Self::Middle),
+            MouseButton::Other(_) => None,
+        }
+    }
+}
+
+/* parse a `--mouse-bind` value, e.g. "left=5" or "wheel-up=2" */
+pub fn parse_mouse_binding(arg: &str) -> Option<(MouseInput, KeyValue)> {
+    let (input, key) = arg.split_once('=')?;
+
+    let input = match input {
+        "left" => MouseInput::Left,
+        "right" => MouseInput::Right,
+        "middle" => MouseInput::Middle,
+        "wheel-up" => MouseInput::WheelUp,
+        "wheel-down" => MouseInput::WheelDown,
+        _ => return None,
+    };
+
+    let key = u8::from_str_radix(key, 16).ok()?;
+    if key > 0xF {
+        return None;
+    }
+
+    Some((input, key))
 }
@@ -0,0 +1,149 @@
+use ggez::graphics::{self, Color, DrawParam, Quad, Rect};
+use std::time::{Duration, Instant};
+
+/* keypad keys as laid out on the COSMAC VIP */
+const LAYOUT: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+const CELL: f32 = 24.0;
+const TOUCH_CELL: f32 = 56.0; // about a fingertip
+const GAP: f32 = 4.0;
+const BORDER: f32 = 2.0;
+const MARGIN: f32 = 8.0;
+
+/* programs usually check a key every few frames, keep it highlighted in between */
+const POLL_HIGHLIGHT: Duration = Duration::from_millis(250);
+
+const RELEASED: Color = Color::new(0.2, 0.2, 0.2, 0.8);
+const PRESSED: Color = Color::new(1.0, 0.85, 0.2, 1.0);
+const POLLED: Color = Color::new(0.2, 0.8, 1.0, 1.0);
+const WAITING: Color = Color::new(1.0, 0.4, 0.2, 1.0); // Fx0A, any key will do
+
+/* keypad widget, showing the held keys and the keys the program is looking at; its keys can be
+ * clicked or touched while it's visible */
+pub struct KeypadOverlay {
+    visible: bool,
+    cell: f32,
+    pressed: [bool; 16],
+    polled: [Option<Instant>; 16], // last time the program checked each key
+    waiting: bool,
+}
+
+impl KeypadOverlay {
+    /* a touch keypad is shown from the start, with keys large enough to be tapped */
+    pub fn new(touch: bool) -> Self {
+        Self {
+            visible: touch,
+            cell: if touch { TOUCH_CELL } else { CELL },
+            pressed: [false; 16],
+            polled: [None; 16],
+            waiting: false,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /* called once per frame; `polled` has a bit set for each key checked since the previous one */
+    pub fn record(&mut self, pressed: [bool; 16], polled: u16, waiting: bool) {
+        let now = Instant::now();
+        for (key, last) in self.polled.iter_mut().enumerate() {
+            if polled & (1 << key) != 0 {
+                *last = Some(now);
+            }
+        }
+        self.pressed = pressed;
+        self.waiting = waiting;
+    }
+
+    /* top-left corner of the cell of each key, in the bottom-right corner of the window, leaving
+     * the left side to the other overlays */
+    fn cells(&self, ctx: &ggez::Context) -> impl Iterator<Item = (u8, f32, f32)> {
+        let (width, height) = ctx.gfx.drawable_size();
+        let size = 4.0 * self.cell + 3.0 * GAP;
+        let origin = [width - size - MARGIN, height - size - MARGIN];
+        let pitch = self.cell + GAP;
+
+        LAYOUT.into_iter().enumerate().flat_map(move |(row, keys)| {
+            keys.into_iter().enumerate().map(move |(col, key)| {
+                let x = origin[0] + col as f32 * pitch;
+                let y = origin[1] + row as f32 * pitch;
+                (key, x, y)
+            })
+        })
+    }
+
+    /* the key under a click, if the keypad is showing */
+    pub fn key_at(&self, ctx: &ggez::Context, x: f32, y: f32) -> Option<u8> {
+        if !self.visible {
+            return None;
+        }
+
+        self.cells(ctx)
+            .find(|(_, left, top)| Rect::new(*left, *top, self.cell, self.cell).contains([x, y]))
+            .map(|(key, _, _)| key)
+    }
+
+    pub fn draw(&self, ctx: &mut ggez::Context) -> ggez::GameResult {
+        if !self.visible {
+            return Ok(());
+        }
+
+        let mut canvas = graphics::Canvas::from_frame(ctx, None);
+        let size = self.cell;
+        let label_scale = (size * 0.6).round();
+
+        for (key, x, y) in self.cells(ctx) {
+            let recently_polled =
+                self.polled[key as usize].is_some_and(|last| last.elapsed() < POLL_HIGHLIGHT);
+            let outline = if self.waiting {
+                Some(WAITING)
+            } else if recently_polled {
+                Some(POLLED)
+            } else {
+                None
+            };
+
+            let mut cell = Rect::new(x, y, size, size);
+            if let Some(color) = outline {
+                canvas.draw(&Quad, DrawParam::default().dest_rect(cell).color(color));
+                cell = Rect::new(
+                    x + BORDER,
+                    y + BORDER,
+                    size - 2.0 * BORDER,
+                    size - 2.0 * BORDER,
+                );
+            }
+            let (fill, label_color) = if self.pressed[key as usize] {
+                (PRESSED, Color::BLACK)
+            } else {
+                (RELEASED, Color::WHITE)
+            };
+            canvas.draw(&Quad, DrawParam::default().dest_rect(cell).color(fill));
+
+            // roughly centered, glyphs are about half as wide as they are tall
+            let mut label = graphics::Text::new(format!("{:X}", key));
+            label.set_scale(label_scale);
+            canvas.draw(
+                &label,
+                DrawParam::default()
+                    .dest([
+                        x + (size - label_scale / 2.0) / 2.0,
+                        y + (size - label_scale) / 2.0,
+                    ])
+                    .color(label_color),
+            );
+        }
+
+        canvas.finish(ctx)
+    }
+}
@@ -0,0 +1,5 @@
+/* The machine without the desktop frontend (window, audio, GPU), for embedding it in tests,
+ * tools or other frontends. The headless modes of the desktop binary run on top of it. */
+#[cfg(feature = "fuzzing")]
+pub mod fuzz;
+pub mod session;
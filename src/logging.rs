@@ -0,0 +1,55 @@
+use crate::error::Error;
+use std::sync::Mutex;
+use tracing::level_filters::LevelFilter;
+
+/* without `--log-level`, only problems are reported */
+pub const DEFAULT_LEVEL: LevelFilter = LevelFilter::WARN;
+
+pub fn parse_level(value: &str) -> Option<LevelFilter> {
+    match value {
+        "off" => Some(LevelFilter::OFF),
+        "error" => Some(LevelFilter::ERROR),
+        "warn" => Some(LevelFilter::WARN),
+        "info" => Some(LevelFilter::INFO),
+        "debug" => Some(LevelFilter::DEBUG),
+        "trace" => Some(LevelFilter::TRACE),
+        _ => None,
+    }
+}
+
+/* install the global subscriber, logging to `file` (appending to it) or to the standard error */
+pub fn init(level: LevelFilter, file: Option<&str>) -> Result<(), Error> {
+    let builder = tracing_subscriber::fmt().with_max_level(level);
+
+    match file {
+        Some(path) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| Error::LogFile(path.to_owned(), e.to_string()))?;
+            builder
+                .with_ansi(false)
+                .with_writer(Mutex::new(file))
+                .init();
+        }
+        None => builder.with_writer(std::io::stderr).init(),
+    }
+
+    install_panic_hook();
+
+    Ok(())
+}
+
+/* A panicking worker thread used to die silently (at most printing to a console that may not
+ * exist), leaving the window on a frozen frame. The panic is now logged within the spans of the
+ * thread it happened in, before the default hook runs.
+ */
+fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+
+    std::panic::set_hook(Box::new(move |info| {
+        tracing::error!("panic: {}", info);
+        default_hook(info);
+    }));
+}
@@ -1,89 +1,182 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod actions;
+mod args;
+mod beep;
+mod bench;
+mod cartridge;
+mod compare;
+mod demo;
+mod download;
 mod emulator;
+mod error;
+mod harness;
+mod headless;
+mod info;
 mod keyboard;
+mod keypad;
+mod logging;
+mod mirror;
+mod osd;
+mod perf;
+mod persist;
+mod rom_file;
+mod rom_settings;
+mod rumble;
 mod screen;
+mod sprites;
+mod summary;
 mod timers;
+mod tui;
+mod twitch;
+mod variant;
+mod visual_beep;
+mod zoom;
 
+use beep::Beep;
 use emulator::*;
-use screen::*;
+use error::Error;
+use keyboard::{KeyValue, MouseInput};
 
+#[derive(Clone, Default)]
 pub struct ProgramOptions {
     schip_compatibility: bool,
+    variant: Option<variant::Variant>, // None: detected from the program
     clip_sprites: bool,
-    clock_speed: u16,
+    clock_speed: u32, // 0 means uncapped
+    volume: u8,
+    beep: Beep,
+    audio: bool, // off: the buzzer is silent, the sound timer counts down all the same
+    visual_beep: Option<visual_beep::VisualBeep>, // the buzzer shown on screen too
+    rumble: u8,  // gamepad vibration while the buzzer sounds, in percent
+    screen_off_after: Option<std::time::Duration>,
+    watchdog: Option<std::time::Duration>, // None: never suggest a reset
+    pause_when_screen_off: bool,
+    run_in_background: bool, // don't pause while the window is unfocused or minimized
+    background_throttle: Option<u8>, // percentage of the clock kept in the background, not paused
+    display_wait: bool,
+    wait_for_release: bool, // Fx0A completes on key release
+    direct_input: bool,     // key events skip the keyboard thread
+    scale: Option<u32>,     // None: restore the previous window size
+    scaling: screen::Scaling,
+    orientation: screen::Orientation,
+    rom_name: String,
+    gpu_backend: ggez::conf::Backend,
+    renderer: screen::Renderer,
+    vsync: bool,            // off: frames are presented as soon as they're drawn
+    shader: Option<String>, // screen shader file, in place of the built-in one
+    watch_shader: bool,     // rebuild the shader when the file changes
+    palette: screen::Palette,
+    border: [u8; 3],
+    background: Option<String>, // image path
+    headless: bool,
+    tui: bool, // run in the terminal instead of a window
+    quirk_ab: bool,
+    lockstep: Option<(bool, bool)>,
+    compare: Option<String>, // reference image of the screen at `max_cycles`
+    max_cycles: u64,
+    fb_out: Option<String>, // final framebuffer of a headless run, PBM or raw
+    mouse_bindings: Vec<(MouseInput, KeyValue)>,
+    key_bindings: Vec<(u32, KeyValue)>, // scancode, keypad key
+    touch_keypad: bool,
+    hotkeys: Vec<(actions::Action, &'static str)>, // in place of the default shortcuts
+    key_diagnostics: bool, // show the scancode of each host key and the held chord
+    turbo: Vec<(KeyValue, u8)>, // autofire rate in Hz, by keypad key
+    summary: Option<String>,
+    stats_out: Option<String>, // JSON counterpart of the summary, with the calls made by the core
+    bench: Option<std::time::Duration>,
+    mirror_host: Option<String>,  // address spectators connect to
+    mirror: Option<String>,       // address of the host to spectate, no ROM is run
+    twitch_plays: Option<String>, // channel whose chat votes on keypad keys
+    disabled_inputs: Vec<String>, // input sources whose events are ignored
+    remote_keys: [bool; 16],      // keypad keys pressed by the spectators instead of the host
+    log_level: Option<tracing::level_filters::LevelFilter>, // None: logging::DEFAULT_LEVEL
+    log_file: Option<String>,
+    rom_entry: Option<String>, // ROM to run from a zip archive holding several
+    playlist_file: Option<String>,
+    playlist: Vec<ProgramOptions>, // entries of `--playlist`, the first one being these options
+    advance_after: Option<std::time::Duration>, // time limit of a playlist entry
     program: Vec<u8>,
 }
 
-fn process_args(args: &Vec<String>) -> Option<ProgramOptions> {
-    if args.is_empty() {
-        return None;
+/* largest scale factor for which the window fits the current monitor, if it can be queried */
+fn max_scale(ctx: &ggez::Context, (width, height): (usize, usize)) -> Option<u32> {
+    let size = ctx.gfx.window().current_monitor()?.size();
+    let max_x = size.width / width as u32;
+    let max_y = size.height / height as u32;
+
+    Some(max_x.min(max_y).max(1))
+}
+
+/* whether a saved window position is still visible, monitors may have changed since */
+fn is_on_screen(ctx: &ggez::Context, (x, y): (i32, i32)) -> bool {
+    ctx.gfx.window().available_monitors().any(|monitor| {
+        let (origin, size) = (monitor.position(), monitor.size());
+        (origin.x..origin.x + size.width as i32).contains(&x)
+            && (origin.y..origin.y + size.height as i32).contains(&y)
+    })
+}
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().collect();
+
+    if args.get(1).map(String::as_str) == Some("test") {
+        std::process::exit(harness::run(&args[2..]));
     }
 
-    let mut program = vec![];
-    let mut schip_compatibility = false;
-    let mut clip_sprites = false;
-    let mut clock_speed = 0;
-
-    // skip processing command line argument if it was the value of the previously processed flag
-    let mut flag_argument = false;
-
-    for (i, arg) in args.iter().enumerate().skip(1) {
-        if !arg.starts_with('-') && !flag_argument {
-            let res = std::fs::read(arg);
-            // only argument not requiring flag
-            program = res.ok()?;
-        } else {
-            flag_argument = false;
-            match &arg[..] {
-                "--clip-sprites" | "-K" => clip_sprites = true,
-                "--schip-opcodes" | "-S" => schip_compatibility = true,
-                "--clock" | "-C" => {
-                    if args.len() > i {
-                        let val = &args[i + 1];
-                        let speed = val.parse::<u16>().ok();
-                        clock_speed = speed?;
-                        flag_argument = true;
-                    } else {
-                        return None;
-                    }
-                }
-                _ => {}
-            }
-        }
+    if args.get(1).map(String::as_str) == Some("info") {
+        std::process::exit(info::run(&args[2..]));
     }
 
-    if program.is_empty() {
-        return None;
+    if args.get(1).map(String::as_str) == Some("sprites") {
+        std::process::exit(sprites::run(&args[2..]));
     }
 
-    if clock_speed == 0 {
-        clock_speed = DEFAULT_CLOCK_SPEED;
+    if args.get(1).map(String::as_str) == Some("run") {
+        args = download::resolve(&args).unwrap_or_else(|e| error::exit_with(e));
     }
 
-    Some(ProgramOptions {
-        schip_compatibility,
-        clip_sprites,
-        clock_speed,
-        program,
-    })
-}
+    let mut parsed = args::parse(&args).unwrap_or_else(|e| error::exit_with(e));
+
+    logging::init(
+        parsed.log_level.unwrap_or(logging::DEFAULT_LEVEL),
+        parsed.log_file.as_deref(),
+    )
+    .unwrap_or_else(|e| error::exit_with(e));
+
+    variant::resolve(&mut parsed);
+
+    if let Some(other_quirks) = parsed.lockstep {
+        std::process::exit(headless::run_lockstep(&parsed, other_quirks));
+    }
+
+    if let Some(reference) = &parsed.compare {
+        let status = compare::run(&parsed, reference).unwrap_or_else(|e| error::exit_with(e));
+        std::process::exit(status);
+    }
 
-fn main() -> ggez::GameResult {
-    let args: Vec<String> = std::env::args().collect();
+    if parsed.quirk_ab {
+        std::process::exit(headless::run_quirk_ab(&parsed));
+    }
 
-    let parsed = process_args(&args);
+    if let (true, Some(duration)) = (parsed.headless, parsed.bench) {
+        std::process::exit(bench::run_headless(&parsed, duration));
+    }
 
-    if parsed.is_none() {
-        println!("ERROR: Invalid arguments!");
-        return Ok(());
+    if parsed.headless {
+        std::process::exit(headless::run(&parsed));
     }
 
-    let parsed = parsed.unwrap();
+    if parsed.tui {
+        tui::run(&parsed).unwrap_or_else(|e| error::exit_with(e));
+        return;
+    }
 
+    let scale = parsed.scale.unwrap_or(screen::DEFAULT_SCALE_FACTOR);
+    let (screen_width, screen_height) = parsed.orientation.size(); // as shown, once rotated
     let window_mode = ggez::conf::WindowMode {
-        width: (chip_8_core::SCREEN_WIDTH * SCREEN_SCALE_FACTOR) as f32,
-        height: (chip_8_core::SCREEN_HEIGHT * SCREEN_SCALE_FACTOR) as f32,
+        width: (screen_width as u32 * scale) as f32,
+        height: (screen_height as u32 * scale) as f32,
         maximized: false,
         fullscreen_type: ggez::conf::FullscreenType::Windowed,
         borderless: false,
@@ -91,7 +184,7 @@ fn main() -> ggez::GameResult {
         max_width: 0.0,
         min_height: 1.0,
         max_height: 0.0,
-        resizable: false,
+        resizable: true,
         visible: true,
         transparent: false,
         resize_on_scale_factor_change: false,
@@ -99,20 +192,78 @@ fn main() -> ggez::GameResult {
     };
 
     let window_setup = ggez::conf::WindowSetup {
-        title: String::from("Chip-8 Emulator"),
+        title: emulator::window_title(&parsed.rom_name, false),
         samples: ggez::conf::NumSamples::One,
-        vsync: true,
-        icon: String::new(), // TODO
+        vsync: parsed.vsync,
+        icon: String::new(), // set below, ggez can only load it from the resource directory
         srgb: false,
     };
 
-    let (ctx, event_loop) = ggez::ContextBuilder::new("chip-8-emulator", "Stefano Ariotta")
+    let (mut ctx, event_loop) = ggez::ContextBuilder::new("chip-8-emulator", "Stefano Ariotta")
         .window_setup(window_setup)
         .window_mode(window_mode)
-        .backend(ggez::conf::Backend::Vulkan)
-        .build()?;
+        .backend(parsed.gpu_backend)
+        .build()
+        .unwrap_or_else(|e| {
+            // ggez opens the audio device along with the window, even with `--no-audio`
+            if let ggez::GameError::AudioError(e) = e {
+                error::exit_with(Error::Audio(format!(
+                    "{} (build with --no-default-features to run without a sound device)",
+                    e
+                )));
+            }
+
+            // winit supports a single event loop per process, so a failed attempt can't be retried
+            // with another backend from here
+            let hint = if parsed.gpu_backend == ggez::conf::Backend::All {
+                "no supported graphics API found"
+            } else {
+                "try another --gpu-backend, or auto"
+            };
+            error::exit_with(Error::Gpu(format!("{} ({})", e, hint)))
+        });
+
+    ctx.gfx.window().set_window_icon(screen::window_icon());
+
+    let saved = persist::WindowState::load(ctx.fs.user_config_dir());
+
+    // the desktop resolution is only known once the window exists; an explicit scale factor takes
+    // precedence over the size saved by the previous run
+    let size = match (parsed.scale, max_scale(&ctx, (screen_width, screen_height))) {
+        (Some(scale), Some(max_scale)) if scale > max_scale => {
+            eprintln!(
+                "Scale factor {} doesn't fit the desktop, using {}",
+                scale, max_scale
+            );
+            Some((
+                (screen_width as u32 * max_scale) as f32,
+                (screen_height as u32 * max_scale) as f32,
+            ))
+        }
+        (Some(_), _) => None,
+        (None, _) => saved.size,
+    };
+    if let Some((width, height)) = size {
+        ctx.gfx
+            .set_mode(window_mode.dimensions(width, height))
+            .unwrap_or_else(|e| error::exit_with(Error::Gpu(e.to_string())));
+    }
+
+    if let Some(position) = saved.position.filter(|p| is_on_screen(&ctx, *p)) {
+        ctx.gfx
+            .window()
+            .set_outer_position(ggez::winit::dpi::PhysicalPosition::new(
+                position.0, position.1,
+            ));
+    }
+
+    if let Some(address) = &parsed.mirror {
+        let client = mirror::MirrorClient::new(&ctx, address, &parsed)
+            .unwrap_or_else(|e| error::exit_with(e));
+        ggez::event::run(ctx, event_loop, client)
+    }
 
-    let emulator = Emulator::new(&ctx, &parsed)?;
+    let emulator = Emulator::new(&ctx, &parsed).unwrap_or_else(|e| error::exit_with(e));
 
     ggez::event::run(ctx, event_loop, emulator)
 }
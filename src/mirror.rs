@@ -0,0 +1,388 @@
+use crate::beep::Buzzer;
+use crate::error::Error;
+use crate::keyboard::{self, InputSource, KeyAction, KeyMessage, KeyValue};
+use crate::osd::Osd;
+use crate::screen::Screen;
+use crate::ProgramOptions;
+use chip_8_core::FrameBuffer;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/* how often the host looks for new spectators when nothing is being sent */
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(100);
+
+/* spectators not reading for this long are dropped, rather than stalling the others */
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/* Spectator protocol: a stream of messages, each a tag byte followed by its payload. A frame is
+ * only 256 bytes, so it is sent whole whenever it changes rather than as a diff. Key events go
+ * the other way; the host runs the only emulated machine, so there is nothing to keep in sync.
+ */
+const TAG_FRAME: u8 = 1; // followed by the framebuffer
+const TAG_BUZZER: u8 = 2; // followed by 0 (off) or 1 (on)
+const TAG_KEY: u8 = 3; // followed by the keypad key and 0 (released) or 1 (pressed)
+
+#[derive(Clone, Copy)]
+#[allow(clippy::large_enum_variant)] // frames are most of the traffic anyway
+enum Message {
+    Frame(FrameBuffer),
+    Buzzer(bool),
+    Key(KeyValue, bool),
+}
+
+impl Message {
+    fn write_to(&self, stream: &mut impl Write) -> std::io::Result<()> {
+        match self {
+            Message::Frame(fb) => {
+                stream.write_all(&[TAG_FRAME])?;
+                stream.write_all(fb)
+            }
+            Message::Buzzer(on) => stream.write_all(&[TAG_BUZZER, *on as u8]),
+            Message::Key(key, pressed) => stream.write_all(&[TAG_KEY, *key, *pressed as u8]),
+        }
+    }
+
+    fn read_from(stream: &mut impl Read) -> std::io::Result<Self> {
+        let mut tag = [0];
+        stream.read_exact(&mut tag)?;
+
+        match tag[0] {
+            TAG_FRAME => {
+                let mut fb = chip_8_core::EMPTY_FRAMEBUFFER;
+                stream.read_exact(&mut fb)?;
+                Ok(Message::Frame(fb))
+            }
+            TAG_BUZZER => {
+                let mut on = [0];
+                stream.read_exact(&mut on)?;
+                Ok(Message::Buzzer(on[0] != 0))
+            }
+            TAG_KEY => {
+                let mut key = [0; 2];
+                stream.read_exact(&mut key)?;
+                if key[0] > 0xF {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "invalid key",
+                    ));
+                }
+                Ok(Message::Key(key[0], key[1] != 0))
+            }
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "unknown message",
+            )),
+        }
+    }
+}
+
+/* ":7000" listens on every interface */
+fn host_address(address: &str) -> String {
+    match address.strip_prefix(':') {
+        Some(port) => format!("0.0.0.0:{}", port),
+        None => address.to_owned(),
+    }
+}
+
+/* host side, fed by the emulator on every update; the network is handled by its own thread */
+pub struct MirrorHost {
+    tx: Sender<Message>,
+    generation: u64,
+    buzzer: bool,
+}
+
+/* keys pressed by the spectators, limited to the ones they own */
+pub struct NetworkInput {
+    rx: Receiver<KeyMessage>,
+    owned: [bool; 16],
+}
+
+impl MirrorHost {
+    pub fn start(address: &str, owned: [bool; 16]) -> Result<(Self, NetworkInput), Error> {
+        let listener = TcpListener::bind(host_address(address))
+            .and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+            .map_err(|e| Error::Mirror(format!("cannot listen on {}: {}", address, e)))?;
+        tracing::info!(address, "waiting for spectators");
+
+        let (tx, rx) = mpsc::channel();
+        let (input_tx, input) = mpsc::channel();
+        std::thread::spawn(move || serve(listener, rx, input_tx));
+
+        let host = Self {
+            tx,
+            generation: u64::MAX,
+            buzzer: false,
+        };
+        Ok((host, NetworkInput { rx: input, owned }))
+    }
+
+    /* only changes are forwarded */
+    pub fn update(&mut self, fb: &FrameBuffer, generation: u64, buzzer: bool) {
+        // the thread only stops when this is dropped
+        if generation != self.generation {
+            self.generation = generation;
+            let _ = self.tx.send(Message::Frame(*fb));
+        }
+        if buzzer != self.buzzer {
+            self.buzzer = buzzer;
+            let _ = self.tx.send(Message::Buzzer(buzzer));
+        }
+    }
+}
+
+impl InputSource for NetworkInput {
+    fn name(&self) -> &'static str {
+        "network"
+    }
+
+    fn poll(&mut self) -> Vec<KeyMessage> {
+        let owned = self.owned;
+        self.rx
+            .try_iter()
+            .filter(|(key, _)| {
+                if !owned[*key as usize] {
+                    tracing::trace!(key, "ignored a spectator key not owned by them");
+                }
+                owned[*key as usize]
+            })
+            .collect()
+    }
+}
+
+/* host thread loop, until the emulator is gone */
+fn serve(listener: TcpListener, rx: Receiver<Message>, input: Sender<KeyMessage>) {
+    let _span = tracing::info_span!("mirror").entered();
+
+    let mut spectators: Vec<TcpStream> = Vec::new();
+    let mut last_frame = Message::Frame(chip_8_core::EMPTY_FRAMEBUFFER);
+    let mut last_buzzer = Message::Buzzer(false);
+
+    loop {
+        match rx.recv_timeout(ACCEPT_INTERVAL) {
+            Ok(message) => {
+                match message {
+                    Message::Frame(_) => last_frame = message,
+                    Message::Buzzer(_) => last_buzzer = message,
+                    Message::Key(..) => continue,
+                }
+                // a spectator that can't keep up or went away is dropped
+                spectators.retain_mut(|stream| message.write_to(stream).is_ok());
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+
+        while let Ok((mut stream, address)) = listener.accept() {
+            tracing::info!(%address, "spectator connected");
+
+            // late spectators start from the current state
+            let res = stream
+                .set_nonblocking(false)
+                .and_then(|_| stream.set_nodelay(true))
+                .and_then(|_| stream.set_write_timeout(Some(WRITE_TIMEOUT)))
+                .and_then(|_| last_frame.write_to(&mut stream))
+                .and_then(|_| last_buzzer.write_to(&mut stream))
+                .and_then(|_| stream.try_clone());
+            if let Ok(reader) = res {
+                let input = input.clone();
+                std::thread::spawn(move || forward_input(reader, input));
+                spectators.push(stream);
+            }
+        }
+    }
+}
+
+/* spectator thread on the host, until it disconnects */
+fn forward_input(mut stream: TcpStream, input: Sender<KeyMessage>) {
+    let _span = tracing::info_span!("mirror").entered();
+    let mut held = [false; 16];
+
+    while let Ok(message) = Message::read_from(&mut stream) {
+        // presses and releases must pair up, whatever the spectator sends
+        if let Message::Key(key, pressed) = message {
+            if held[key as usize] == pressed {
+                continue;
+            }
+            held[key as usize] = pressed;
+            let action = if pressed {
+                KeyAction::Pressed
+            } else {
+                KeyAction::Released
+            };
+            if input.send((key, action)).is_err() {
+                return;
+            }
+        }
+    }
+
+    // nobody is left to release what was held
+    tracing::info!("spectator disconnected");
+    for key in (0..16).filter(|key| held[*key as usize]) {
+        let _ = input.send((key, KeyAction::Released));
+    }
+}
+
+/* state received from the host */
+struct Mirrored {
+    fb: FrameBuffer,
+    generation: u64,
+    buzzer: bool,
+}
+
+/* `--mirror` window, showing what a host instance is running */
+pub struct MirrorClient {
+    screen: Screen,
+    stream: TcpStream, // to send key events
+    key_bindings: Vec<(u32, KeyValue)>,
+    sound: Buzzer,
+    state: Arc<Mutex<Mirrored>>,
+    disconnected: Arc<AtomicBool>,
+    osd: Osd,
+    frame_dirty: bool,
+    buzzing: bool,
+}
+
+impl MirrorClient {
+    pub fn new(
+        ctx: &ggez::Context,
+        address: &str,
+        options: &ProgramOptions,
+    ) -> Result<Self, Error> {
+        let screen = Screen::new(ctx, options)?;
+
+        let mut sound = Buzzer::new(ctx, options.beep, options.audio)?;
+        sound.set_volume(options.volume);
+
+        let stream = TcpStream::connect(address)
+            .and_then(|stream| stream.set_nodelay(true).map(|_| stream))
+            .map_err(|e| Error::Mirror(format!("cannot connect to {}: {}", address, e)))?;
+        let mut reader = stream
+            .try_clone()
+            .map_err(|e| Error::Mirror(e.to_string()))?;
+
+        let state = Arc::new(Mutex::new(Mirrored {
+            fb: chip_8_core::EMPTY_FRAMEBUFFER,
+            generation: 0,
+            buzzer: false,
+        }));
+        let disconnected = Arc::new(AtomicBool::new(false));
+
+        let thread_state = Arc::clone(&state);
+        let thread_disconnected = Arc::clone(&disconnected);
+        std::thread::spawn(move || {
+            let _span = tracing::info_span!("mirror").entered();
+
+            while let Ok(message) = Message::read_from(&mut reader) {
+                let mut state = match thread_state.lock() {
+                    Ok(state) => state,
+                    Err(_) => return,
+                };
+                match message {
+                    Message::Frame(fb) => {
+                        state.fb = fb;
+                        state.generation += 1;
+                    }
+                    Message::Buzzer(on) => state.buzzer = on,
+                    Message::Key(..) => {}
+                }
+            }
+
+            tracing::info!("host disconnected");
+            thread_disconnected.store(true, Relaxed);
+        });
+
+        Ok(Self {
+            screen,
+            stream,
+            key_bindings: options.key_bindings.clone(),
+            sound,
+            state,
+            disconnected,
+            osd: Osd::default(),
+            frame_dirty: true,
+            buzzing: false,
+        })
+    }
+}
+
+impl ggez::event::EventHandler<ggez::GameError> for MirrorClient {
+    fn update(&mut self, _ctx: &mut ggez::Context) -> ggez::GameResult {
+        let disconnected = self.disconnected.load(Relaxed);
+        let buzzer = self.state.lock().map_err(Error::sync("mirror"))?.buzzer && !disconnected;
+
+        if buzzer != self.buzzing {
+            self.buzzing = buzzer;
+            if buzzer {
+                self.sound.resume();
+            } else {
+                self.sound.pause();
+            }
+        }
+
+        if disconnected {
+            self.osd.pin("Host disconnected");
+        }
+
+        Ok(())
+    }
+
+    /* the host decides which keys spectators may press, the others are ignored there */
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut ggez::Context,
+        input: ggez::input::keyboard::KeyInput,
+        repeated: bool,
+    ) -> ggez::GameResult {
+        if let Some(key) = keyboard::bound_key(&self.key_bindings, input.scancode) {
+            if !repeated {
+                // a broken connection is noticed by the reader thread
+                let _ = Message::Key(key, true).write_to(&mut self.stream);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn key_up_event(
+        &mut self,
+        _ctx: &mut ggez::Context,
+        input: ggez::input::keyboard::KeyInput,
+    ) -> ggez::GameResult {
+        if let Some(key) = keyboard::bound_key(&self.key_bindings, input.scancode) {
+            let _ = Message::Key(key, false).write_to(&mut self.stream);
+        }
+
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut ggez::Context) -> ggez::GameResult {
+        let (fb, generation) = {
+            let state = self.state.lock().map_err(Error::sync("mirror"))?;
+            (state.fb, state.generation)
+        };
+
+        let overlays = self.osd.is_visible();
+        self.screen
+            .draw(ctx, &fb, generation, overlays || self.frame_dirty)?;
+        self.frame_dirty = overlays;
+
+        self.osd.draw(ctx)
+    }
+
+    fn resize_event(
+        &mut self,
+        ctx: &mut ggez::Context,
+        width: f32,
+        height: f32,
+    ) -> ggez::GameResult {
+        self.screen.resize(ctx, width, height);
+        self.frame_dirty = true;
+
+        Ok(())
+    }
+}
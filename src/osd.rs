@@ -0,0 +1,68 @@
+use ggez::graphics;
+use std::time::{Duration, Instant};
+
+/* how long a message stays on screen, unless specified otherwise */
+const MESSAGE_DURATION: Duration = Duration::from_millis(1500);
+
+/* on-screen display for short-lived status messages, drawn on top of the emulated screen */
+#[derive(Default)]
+pub struct Osd {
+    message: Option<(String, Instant)>, // text, expiration
+    pinned: Option<String>,             // shown in place of messages until unpinned
+}
+
+impl Osd {
+    pub fn show(&mut self, text: impl Into<String>) {
+        self.show_for(text, MESSAGE_DURATION);
+    }
+
+    pub fn show_for(&mut self, text: impl Into<String>, duration: Duration) {
+        self.message = Some((text.into(), Instant::now() + duration));
+    }
+
+    /* for errors, which stay on screen until dealt with */
+    pub fn pin(&mut self, text: impl Into<String>) {
+        self.pinned = Some(text.into());
+    }
+
+    pub fn unpin(&mut self) {
+        self.pinned = None;
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.pinned.is_some()
+            || matches!(&self.message, Some((_, expiration)) if Instant::now() < *expiration)
+    }
+
+    pub fn draw(&mut self, ctx: &mut ggez::Context) -> ggez::GameResult {
+        if matches!(&self.message, Some((_, expiration)) if Instant::now() >= *expiration) {
+            self.message = None;
+        }
+
+        let (text, color) = match (&self.pinned, &self.message) {
+            (Some(text), _) => (text, graphics::Color::RED),
+            (None, Some((text, _))) => (text, graphics::Color::YELLOW),
+            (None, None) => return Ok(()),
+        };
+
+        // load the frame drawn by the screen pipeline instead of clearing it
+        let mut canvas = graphics::Canvas::from_frame(ctx, None);
+
+        let mut text = graphics::Text::new(text.as_str());
+        text.set_scale(24.0);
+
+        // drop shadow, to keep the text readable on top of lit pixels
+        canvas.draw(
+            &text,
+            graphics::DrawParam::default()
+                .dest([10.0, 10.0])
+                .color(graphics::Color::BLACK),
+        );
+        canvas.draw(
+            &text,
+            graphics::DrawParam::default().dest([8.0, 8.0]).color(color),
+        );
+
+        canvas.finish(ctx)
+    }
+}
@@ -0,0 +1,109 @@
+use ggez::graphics;
+use std::time::{Duration, Instant};
+
+/* statistics are averaged over windows of this length, so that the numbers stay readable */
+const SAMPLE_WINDOW: Duration = Duration::from_millis(500);
+
+/* performance overlay, showing whether the requested clock speed is actually being met */
+pub struct PerfOverlay {
+    visible: bool,
+    target_hz: u32, // 0 means uncapped
+    window_start: Instant,
+    instructions: u64,
+    last_tick: Instant,
+    tick_intervals: Vec<f64>, // in ms, for the current window
+    text: String,             // statistics of the last completed window
+}
+
+impl PerfOverlay {
+    pub fn new(target_hz: u32) -> Self {
+        Self {
+            visible: false,
+            target_hz,
+            window_start: Instant::now(),
+            instructions: 0,
+            last_tick: Instant::now(),
+            tick_intervals: Vec::new(),
+            text: String::new(),
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /* called once per frame, with the number of instructions executed since the previous one */
+    pub fn record_tick(&mut self, instructions: u64) {
+        let now = Instant::now();
+        self.tick_intervals
+            .push((now - self.last_tick).as_secs_f64() * 1000.0);
+        self.last_tick = now;
+        self.instructions += instructions;
+
+        let elapsed = now - self.window_start;
+        if elapsed < SAMPLE_WINDOW {
+            return;
+        }
+
+        let ips = self.instructions as f64 / elapsed.as_secs_f64();
+
+        // jitter as the standard deviation of the tick duration
+        let n = self.tick_intervals.len() as f64;
+        let mean = self.tick_intervals.iter().sum::<f64>() / n;
+        let variance = self
+            .tick_intervals
+            .iter()
+            .map(|x| (x - mean) * (x - mean))
+            .sum::<f64>()
+            / n;
+
+        let target = match self.target_hz {
+            0 => "uncapped".to_owned(),
+            hz => format!("{} Hz", hz),
+        };
+        self.text = format!(
+            "IPS: {:.0} / {}\nJitter: {:.2} ms",
+            ips,
+            target,
+            variance.sqrt()
+        );
+
+        self.window_start = now;
+        self.instructions = 0;
+        self.tick_intervals.clear();
+    }
+
+    pub fn draw(&self, ctx: &mut ggez::Context) -> ggez::GameResult {
+        if !self.visible {
+            return Ok(());
+        }
+
+        let mut canvas = graphics::Canvas::from_frame(ctx, None);
+
+        let mut text = graphics::Text::new(format!("FPS: {:.1}\n{}", ctx.time.fps(), self.text));
+        text.set_scale(16.0);
+
+        // bottom-left corner, leaving the top one to the OSD
+        let (_, height) = ctx.gfx.drawable_size();
+        let dest = [8.0, height - 3.0 * 20.0 - 8.0];
+
+        canvas.draw(
+            &text,
+            graphics::DrawParam::default()
+                .dest([dest[0] + 1.0, dest[1] + 1.0])
+                .color(graphics::Color::BLACK),
+        );
+        canvas.draw(
+            &text,
+            graphics::DrawParam::default()
+                .dest(dest)
+                .color(graphics::Color::GREEN),
+        );
+
+        canvas.finish(ctx)
+    }
+}
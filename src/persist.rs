@@ -0,0 +1,194 @@
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+
+const FILE_NAME: &str = "window.txt";
+const PLAYS_FILE_NAME: &str = "plays.txt";
+
+/* window geometry saved between runs, as `key=value` lines in the user config directory */
+#[derive(Default)]
+pub struct WindowState {
+    pub position: Option<(i32, i32)>, // outer, in physical pixels
+    pub size: Option<(f32, f32)>,     // inner, in physical pixels
+}
+
+fn parse_pair<T: std::str::FromStr>(value: &str) -> Option<(T, T)> {
+    let (a, b) = value.split_once(',')?;
+    Some((a.trim().parse().ok()?, b.trim().parse().ok()?))
+}
+
+impl WindowState {
+    /* a missing or unreadable file just means there's nothing to restore */
+    pub fn load(dir: &Path) -> Self {
+        let mut res = Self::default();
+
+        let text = match std::fs::read_to_string(dir.join(FILE_NAME)) {
+            Ok(text) => text,
+            Err(_) => return res,
+        };
+
+        for line in text.lines() {
+            match line.split_once('=') {
+                Some(("position", value)) => res.position = parse_pair(value),
+                Some(("size", value)) => res.size = parse_pair(value),
+                _ => {}
+            }
+        }
+
+        res
+    }
+
+    pub fn save(&self, dir: &Path) -> std::io::Result<()> {
+        let mut text = String::new();
+        if let Some((x, y)) = self.position {
+            text.push_str(&format!("position={},{}\n", x, y));
+        }
+        if let Some((width, height)) = self.size {
+            text.push_str(&format!("size={},{}\n", width, height));
+        }
+
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(dir.join(FILE_NAME), text)
+    }
+}
+
+/* Play history of a ROM, keyed by the hash of its contents so that renamed copies share it. Saved
+ * as `<hash> <launches> <seconds played> <last launch, Unix time>` lines in the user config
+ * directory.
+ */
+#[derive(Clone, Copy, Default)]
+pub struct PlayStats {
+    pub launches: u64,
+    pub play_time: Duration,
+    pub last_played: Option<SystemTime>,
+}
+
+fn parse_plays_line(line: &str) -> Option<(u64, PlayStats)> {
+    let mut fields = line.split_whitespace();
+    let hash = u64::from_str_radix(fields.next()?, 16).ok()?;
+    let launches = fields.next()?.parse().ok()?;
+    let play_time = Duration::from_secs(fields.next()?.parse().ok()?);
+    let last_played = SystemTime::UNIX_EPOCH + Duration::from_secs(fields.next()?.parse().ok()?);
+
+    Some((
+        hash,
+        PlayStats {
+            launches,
+            play_time,
+            last_played: Some(last_played),
+        },
+    ))
+}
+
+impl PlayStats {
+    /* a missing or unreadable file just means the ROM wasn't played before */
+    pub fn load(dir: &Path, rom_hash: u64) -> Self {
+        std::fs::read_to_string(dir.join(PLAYS_FILE_NAME))
+            .ok()
+            .and_then(|text| {
+                text.lines()
+                    .filter_map(parse_plays_line)
+                    .find(|(hash, _)| *hash == rom_hash)
+            })
+            .map_or_else(Self::default, |(_, stats)| stats)
+    }
+
+    /* rewrites the line of the ROM, leaving the others as they are */
+    pub fn save(&self, dir: &Path, rom_hash: u64) -> std::io::Result<()> {
+        let path = dir.join(PLAYS_FILE_NAME);
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+
+        let mut lines: Vec<String> = text
+            .lines()
+            .filter(|line| !matches!(parse_plays_line(line), Some((hash, _)) if hash == rom_hash))
+            .map(str::to_owned)
+            .collect();
+        let last_played = self
+            .last_played
+            .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        lines.push(format!(
+            "{:016x} {} {} {}",
+            rom_hash,
+            self.launches,
+            self.play_time.as_secs(),
+            last_played.as_secs()
+        ));
+
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(path, lines.join("\n") + "\n")
+    }
+
+    /* e.g. "Played 3 times, 1 h 20 min in total, last yesterday" */
+    pub fn describe(&self) -> String {
+        let minutes = self.play_time.as_secs() / 60;
+        let total = match minutes {
+            0 => "less than a minute".to_owned(),
+            1..=59 => format!("{} min", minutes),
+            _ => format!("{} h {} min", minutes / 60, minutes % 60),
+        };
+        let days = self
+            .last_played
+            .and_then(|time| time.elapsed().ok())
+            .map_or(0, |elapsed| elapsed.as_secs() / (24 * 60 * 60));
+        let last = match days {
+            0 => "today".to_owned(),
+            1 => "yesterday".to_owned(),
+            days => format!("{} days ago", days),
+        };
+
+        format!(
+            "Played {} time{}, {} in total, last {}",
+            self.launches,
+            if self.launches == 1 { "" } else { "s" },
+            total,
+            last
+        )
+    }
+}
+
+/* the play history of the running ROM, counting this launch */
+pub struct PlaySession {
+    rom_hash: u64,
+    previous: PlayStats,
+    started: Instant,
+}
+
+impl PlaySession {
+    /* the launch is saved right away, it counts even if the session doesn't end cleanly */
+    pub fn start(dir: &Path, program: &[u8]) -> Self {
+        let rom_hash = crate::headless::fnv1a(program);
+        let previous = PlayStats::load(dir, rom_hash);
+
+        let res = Self {
+            rom_hash,
+            previous,
+            started: Instant::now(),
+        };
+        if let Err(e) = res.stats().save(dir, rom_hash) {
+            tracing::warn!("cannot save the play history: {}", e);
+        }
+
+        res
+    }
+
+    /* as it was before this launch */
+    pub fn previous(&self) -> &PlayStats {
+        &self.previous
+    }
+
+    fn stats(&self) -> PlayStats {
+        PlayStats {
+            launches: self.previous.launches + 1,
+            play_time: self.previous.play_time + self.started.elapsed(),
+            last_played: Some(SystemTime::now()),
+        }
+    }
+
+    pub fn save(&self, dir: &Path) -> std::io::Result<()> {
+        self.stats().save(dir, self.rom_hash)
+    }
+}
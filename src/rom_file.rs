@@ -0,0 +1,121 @@
+use crate::error::{Error, MAX_ROM_SIZE};
+use std::io::Read;
+use std::path::Path;
+
+/* entries of an archive taken for ROMs */
+pub const ROM_EXTENSIONS: [&str; 2] = ["ch8", "c8"];
+
+/* A ROM read from its own file, or from a `.zip` archive holding it, as ROM packs are commonly
+ * distributed. Settings shipped in the archive are picked up like the ones next to a plain file.
+ */
+pub struct RomFile {
+    pub program: Vec<u8>,
+    pub stem: String,                      // file name without the extension
+    pub sidecar: Option<(String, String)>, // name and contents of the `.json` in the archive
+}
+
+fn invalid(path: &Path, reason: impl Into<String>) -> Error {
+    Error::InvalidArchive(path.to_string_lossy().into_owned(), reason.into())
+}
+
+pub fn has_extension(name: &str, extensions: &[&str]) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| extensions.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+}
+
+fn stem(name: &str) -> String {
+    Path::new(name).file_stem().map_or_else(
+        || name.to_owned(),
+        |stem| stem.to_string_lossy().into_owned(),
+    )
+}
+
+impl RomFile {
+    /* the core panics on programs that don't fit in memory past the interpreter */
+    pub fn check_size(&self, path: &Path) -> Result<(), Error> {
+        if self.program.len() > MAX_ROM_SIZE {
+            return Err(Error::RomTooLarge(
+                path.to_string_lossy().into_owned(),
+                self.program.len(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /* `entry` picks the ROM in archives holding several, by name with or without its folder */
+    pub fn load(path: &Path, entry: Option<&str>) -> Result<Self, Error> {
+        let not_found = || Error::RomNotFound(path.to_string_lossy().into_owned());
+
+        if !has_extension(&path.to_string_lossy(), &["zip"]) {
+            return Ok(Self {
+                program: std::fs::read(path).map_err(|_| not_found())?,
+                stem: stem(&path.to_string_lossy()),
+                sidecar: None,
+            });
+        }
+
+        let file = std::fs::File::open(path).map_err(|_| not_found())?;
+        let mut archive = zip::ZipArchive::new(file).map_err(|e| invalid(path, e.to_string()))?;
+
+        let mut roms: Vec<String> = archive
+            .file_names()
+            .filter(|name| has_extension(name, &ROM_EXTENSIONS))
+            .map(str::to_owned)
+            .collect();
+        roms.sort();
+
+        let name = match (entry, roms.as_slice()) {
+            (Some(entry), _) => roms
+                .iter()
+                .find(|name| *name == entry || name.rsplit('/').next() == Some(entry))
+                .ok_or_else(|| invalid(path, format!("no ROM named \"{}\" inside", entry)))?,
+            (None, [name]) => name,
+            (None, []) => return Err(invalid(path, "no .ch8 or .c8 ROM inside")),
+            (None, _) => {
+                return Err(invalid(
+                    path,
+                    format!("pick one of its ROMs with --entry: {}", roms.join(", ")),
+                ))
+            }
+        }
+        .clone();
+
+        let program = {
+            let rom = archive
+                .by_name(&name)
+                .map_err(|e| invalid(path, e.to_string()))?;
+            // checked before inflating it, an entry can claim any size
+            if rom.size() > MAX_ROM_SIZE as u64 {
+                return Err(Error::RomTooLarge(name, rom.size() as usize));
+            }
+            let mut program = Vec::new();
+            rom.take(MAX_ROM_SIZE as u64)
+                .read_to_end(&mut program)
+                .map_err(|e| invalid(path, e.to_string()))?;
+            program
+        };
+
+        let sidecar_name = match name.rsplit_once('.') {
+            Some((base, _)) => format!("{}.json", base),
+            None => format!("{}.json", name),
+        };
+        let sidecar = match archive.by_name(&sidecar_name) {
+            Ok(mut file) => {
+                let mut text = String::new();
+                file.read_to_string(&mut text)
+                    .map_err(|e| invalid(path, e.to_string()))?;
+                Some((sidecar_name, text))
+            }
+            Err(_) => None,
+        };
+
+        Ok(Self {
+            program,
+            stem: stem(&name),
+            sidecar,
+        })
+    }
+}
@@ -0,0 +1,191 @@
+use crate::error::Error;
+use crate::keyboard::{self, KeyValue, MouseInput};
+use crate::screen;
+use crate::variant::Variant;
+use crate::ProgramOptions;
+use std::path::Path;
+
+/* Per-ROM overrides in a `.toml` file with the same name as the ROM, for curated collections:
+ *   clock = 700
+ *   clip-sprites = true
+ *   variant = "schip"
+ *   display-wait = false
+ *   wait-release = true
+ *   palette = ["#000000", "#33FF66"]   # off, on; or a preset, e.g. "high-contrast"
+ *   border = "#202020"
+ *   background = "frame.png"           # next to the ROM
+ *   [keys]           # keypad key = host key
+ *   5 = "space"
+ *   [mouse]          # mouse input = keypad key
+ *   left = "5"
+ */
+#[derive(Default)]
+pub struct RomSettings {
+    clock_speed: Option<u32>,
+    clip_sprites: Option<bool>,
+    variant: Option<Variant>,
+    display_wait: Option<bool>,
+    wait_for_release: Option<bool>,
+    palette: Vec<[u8; 3]>, // first entries of the palette
+    border: Option<[u8; 3]>,
+    background: Option<String>, // image path, relative to the ROM
+    key_bindings: Vec<(u32, KeyValue)>,
+    mouse_bindings: Vec<(MouseInput, KeyValue)>,
+}
+
+fn parse_key(key: &str) -> Option<KeyValue> {
+    u8::from_str_radix(key, 16).ok().filter(|key| *key <= 0xF)
+}
+
+impl RomSettings {
+    /* `None` if the ROM has no settings file */
+    pub fn load(rom_path: &Path) -> Result<Option<Self>, Error> {
+        let path = rom_path.with_extension("toml");
+        let invalid =
+            |reason: String| Error::InvalidSidecar(path.to_string_lossy().into_owned(), reason);
+
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(invalid(e.to_string())),
+        };
+
+        let table: toml::Table = text
+            .parse()
+            .map_err(|e: toml::de::Error| invalid(e.to_string()))?;
+        let mut res = Self::from_table(&table).map_err(invalid)?;
+        if let Some(background) = &mut res.background {
+            let dir = rom_path.parent().unwrap_or(Path::new(""));
+            *background = dir.join(&*background).to_string_lossy().into_owned();
+        }
+        Ok(Some(res))
+    }
+
+    fn from_table(table: &toml::Table) -> Result<Self, String> {
+        let mut res = Self::default();
+
+        let expected = |key: &str, what: &str| format!("\"{}\" must be {}", key, what);
+
+        for (key, value) in table {
+            match key.as_str() {
+                "clock" => {
+                    let clock_speed = value
+                        .as_integer()
+                        .and_then(|x| u32::try_from(x).ok())
+                        .filter(|x| *x == 0 || *x >= 50)
+                        .ok_or_else(|| expected(key, "a clock speed of at least 50 Hz, or 0"))?;
+                    res.clock_speed = Some(clock_speed);
+                }
+                "clip-sprites" => {
+                    res.clip_sprites =
+                        Some(value.as_bool().ok_or_else(|| expected(key, "a boolean"))?)
+                }
+                "display-wait" => {
+                    res.display_wait =
+                        Some(value.as_bool().ok_or_else(|| expected(key, "a boolean"))?)
+                }
+                "wait-release" => {
+                    res.wait_for_release =
+                        Some(value.as_bool().ok_or_else(|| expected(key, "a boolean"))?)
+                }
+                "palette" => {
+                    let preset = |name: &str| {
+                        screen::PRESETS
+                            .iter()
+                            .find(|(preset, _)| *preset == name)
+                            .map(|(_, colors)| colors.to_vec())
+                    };
+                    res.palette = match value {
+                        toml::Value::String(name) => preset(name),
+                        value => value.as_array().and_then(|colors| {
+                            colors
+                                .iter()
+                                .map(|color| color.as_str().and_then(screen::parse_color))
+                                .collect::<Option<Vec<_>>>()
+                        }),
+                    }
+                    .filter(|colors| colors.len() <= screen::PALETTE_SIZE)
+                    .ok_or_else(|| {
+                        expected(key, "a list of at most 16 \"#RRGGBB\" colors, or a preset")
+                    })?;
+                }
+                "border" => {
+                    let border = value
+                        .as_str()
+                        .and_then(screen::parse_color)
+                        .ok_or_else(|| expected(key, "a \"#RRGGBB\" color"))?;
+                    res.border = Some(border);
+                }
+                "background" => {
+                    let path = value.as_str().ok_or_else(|| expected(key, "a path"))?;
+                    res.background = Some(path.to_owned());
+                }
+                "variant" => {
+                    let variant = value
+                        .as_str()
+                        .and_then(Variant::parse)
+                        .ok_or_else(|| expected(key, "\"chip8\" or \"schip\""))?;
+                    res.variant = Some(variant);
+                }
+                "keys" => {
+                    let keys = value.as_table().ok_or_else(|| expected(key, "a table"))?;
+                    for (keypad, host) in keys {
+                        let binding =
+                            parse_key(keypad).zip(host.as_str().and_then(keyboard::parse_host_key));
+                        let (keypad, host) = binding.ok_or_else(|| {
+                            format!("invalid key binding \"{} = {}\"", keypad, host)
+                        })?;
+                        res.key_bindings.push((host, keypad));
+                    }
+                }
+                "mouse" => {
+                    let mouse = value.as_table().ok_or_else(|| expected(key, "a table"))?;
+                    for (input, keypad) in mouse {
+                        let binding = keypad
+                            .as_str()
+                            .and_then(|keypad| {
+                                keyboard::parse_mouse_binding(&format!("{}={}", input, keypad))
+                            })
+                            .ok_or_else(|| {
+                                format!("invalid mouse binding \"{} = {}\"", input, keypad)
+                            })?;
+                        res.mouse_bindings.push(binding);
+                    }
+                }
+                _ => return Err(format!("unknown setting \"{}\"", key)),
+            }
+        }
+
+        Ok(res)
+    }
+
+    /* to be applied before the command line flags, which take precedence */
+    pub fn apply(&self, options: &mut ProgramOptions) {
+        if let Some(clock_speed) = self.clock_speed {
+            options.clock_speed = clock_speed;
+        }
+        if let Some(clip_sprites) = self.clip_sprites {
+            options.clip_sprites = clip_sprites;
+        }
+        if let Some(variant) = self.variant {
+            options.variant = Some(variant);
+        }
+        if let Some(display_wait) = self.display_wait {
+            options.display_wait = display_wait;
+        }
+        if let Some(wait_for_release) = self.wait_for_release {
+            options.wait_for_release = wait_for_release;
+        }
+        options.palette.set(&self.palette);
+        if let Some(border) = self.border {
+            options.border = border;
+        }
+        if let Some(background) = &self.background {
+            options.background = Some(background.clone());
+        }
+        options.key_bindings.extend_from_slice(&self.key_bindings);
+        options
+            .mouse_bindings
+            .extend_from_slice(&self.mouse_bindings);
+    }
+}
@@ -0,0 +1,75 @@
+use ggez::input::gamepad::gilrs::{self, ff, Gilrs};
+
+/* `--rumble`: the connected gamepads vibrate while the buzzer sounds */
+pub struct Rumble {
+    gilrs: Gilrs, // its own instance, ggez doesn't give access to the one it polls
+    magnitude: u16,
+    effect: Option<Option<ff::Effect>>, // None: not rumbling, Some(None): no gamepad can
+}
+
+impl Rumble {
+    /* intensity in percent, None if gamepads aren't supported on this system */
+    pub fn new(intensity: u8) -> Option<Self> {
+        let gilrs = match Gilrs::new() {
+            Ok(gilrs) => gilrs,
+            Err(e) => {
+                tracing::warn!("gamepad rumble unavailable: {}", e);
+                return None;
+            }
+        };
+
+        Some(Self {
+            gilrs,
+            magnitude: (u16::MAX as u32 * intensity.min(100) as u32 / 100) as u16,
+            effect: None,
+        })
+    }
+
+    /* once per frame, with the state of the buzzer; the effect stops when dropped */
+    pub fn update(&mut self, buzzing: bool) {
+        // gamepads plugged in or out are only noticed through their events
+        while let Some(gilrs::Event { event, .. }) = self.gilrs.next_event() {
+            if matches!(
+                event,
+                gilrs::EventType::Connected | gilrs::EventType::Disconnected
+            ) {
+                self.effect = None;
+            }
+        }
+
+        if !buzzing {
+            self.effect = None;
+        } else if self.effect.is_none() {
+            self.effect = Some(self.start().unwrap_or_else(|e| {
+                tracing::warn!("cannot start the gamepad rumble: {}", e);
+                None
+            }));
+        }
+    }
+
+    fn start(&mut self) -> Result<Option<ff::Effect>, ff::Error> {
+        let gamepads: Vec<_> = self
+            .gilrs
+            .gamepads()
+            .filter(|(_, gamepad)| gamepad.is_ff_supported())
+            .map(|(id, _)| id)
+            .collect();
+        if gamepads.is_empty() {
+            return Ok(None);
+        }
+
+        // a constant effect, repeated until stopped
+        let effect = ff::EffectBuilder::new()
+            .add_effect(ff::BaseEffect {
+                kind: ff::BaseEffectType::Strong {
+                    magnitude: self.magnitude,
+                },
+                ..Default::default()
+            })
+            .gamepads(&gamepads)
+            .finish(&mut self.gilrs)?;
+        effect.play()?;
+
+        Ok(Some(effect))
+    }
+}
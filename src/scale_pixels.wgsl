@@ -1,6 +1,31 @@
+// Interface of `--shader` files: the same bindings and entry points as below. The vertex shader
+// covers the window with a single triangle, the fragment shader colors every window pixel.
+
+struct Params {
+    // top-left corner of the emulated screen in the window, in physical pixels
+    offset: vec2<f32>,
+    // size of an emulated pixel, in physical pixels
+    scale: f32,
+    // 0: nearest-neighbour, 1: bilinear
+    smooth_filter: u32,
+    // size of the window, in physical pixels
+    resolution: vec2<f32>,
+    // seconds since the pipeline was created, custom shaders are redrawn on every frame
+    time: f32,
+    // bits 0-1: clockwise quarter turns, then mirrored (bit 2) and upside down (bit 3)
+    orientation: u32,
+    // accessibility zoom: center of the part shown, in emulated pixels of the screen as shown
+    zoom_center: vec2<f32>,
+    // magnification, 1.0 when the whole screen is shown
+    zoom: f32,
+    // linear color of each pixel value: 0 off, 1 on (2 and 3 are reserved for a second plane)
+    palette: array<vec4<f32>, 16>,
+}
+
 @group(0) @binding(0)
-var<uniform> scale: u32;
+var<uniform> params: Params;
 
+// the 64x32 screen, 1 bit per pixel: two words per row, most significant bit on the left
 @group(0) @binding(1)
 var<storage> fb_image: array<u32, 64>;
 
@@ -9,26 +34,74 @@ fn vs_main(@location(0) pos: vec3<f32>) -> @builtin(position) vec4<f32> {
     return vec4(pos, 1.0);
 }
 
-@fragment
-fn fs_main(@builtin(position) in: vec4<f32>) -> @location(0) vec4<f32> {
-    var xy: vec2<f32> = in.xy;
-
-    var scaled_x: u32 = u32(xy.x)/scale;
-    var scaled_y: u32 = u32(xy.y)/scale;
+// 1.0 if the pixel is on, 0.0 otherwise; out of range coordinates are clamped to the edge
+fn pixel(x: i32, y: i32) -> f32 {
+    var px: u32 = u32(clamp(x, 0, 63));
+    var py: u32 = u32(clamp(y, 0, 31));
 
     // first or second "column" of the pixel row
-    var col_x: u32 = scaled_x / 32u;
+    var col_x: u32 = px / 32u;
 
-    var sprite_chunk: u32 = fb_image[col_x + 2u * scaled_y];
+    var sprite_chunk: u32 = fb_image[col_x + 2u * py];
 
     // mask the bit corresponding the x coordinate (left to right)
-    var byte_x: u32 = 1u << (31u - (scaled_x % 32u));
+    var byte_x: u32 = 1u << (31u - (px % 32u));
+
+    if (0u == (byte_x & sprite_chunk)) {
+        return 0.0;
+    }
+
+    return 1.0;
+}
+
+@fragment
+fn fs_main(@builtin(position) in: vec4<f32>) -> @location(0) vec4<f32> {
+    // position on the screen as shown, in emulated pixels
+    var xy: vec2<f32> = (in.xy - params.offset) / params.scale;
+    var turns: u32 = params.orientation & 3u;
+    var size: vec2<f32> = vec2<f32>(64.0, 32.0);
+    if (turns % 2u == 1u) {
+        size = vec2<f32>(32.0, 64.0);
+    }
+
+    // letterboxing, the border was drawn beforehand
+    if (xy.x < 0.0 || xy.y < 0.0 || xy.x >= size.x || xy.y >= size.y) {
+        discard;
+    }
+
+    // the magnified part
+    xy = params.zoom_center + (xy - size * 0.5) / params.zoom;
+
+    // back to the emulated screen: undo the mirroring, then the rotation
+    if ((params.orientation & 4u) != 0u) {
+        xy.x = size.x - xy.x;
+    }
+    if ((params.orientation & 8u) != 0u) {
+        xy.y = size.y - xy.y;
+    }
+    if (turns == 1u) {
+        xy = vec2<f32>(xy.y, 32.0 - xy.x);
+    } else if (turns == 2u) {
+        xy = vec2<f32>(64.0 - xy.x, 32.0 - xy.y);
+    } else if (turns == 3u) {
+        xy = vec2<f32>(64.0 - xy.y, xy.x);
+    }
 
-    var is_off: bool = 0u == (byte_x & sprite_chunk);
+    var value: f32;
+    if (params.smooth_filter == 0u) {
+        value = pixel(i32(xy.x), i32(xy.y));
+    } else {
+        // interpolate between the centers of the four closest pixels
+        var q: vec2<f32> = xy - vec2<f32>(0.5, 0.5);
+        var base: vec2<f32> = floor(q);
+        var t: vec2<f32> = q - base;
+        var x: i32 = i32(base.x);
+        var y: i32 = i32(base.y);
 
-    if (is_off) {
-        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+        var top: f32 = mix(pixel(x, y), pixel(x + 1, y), t.x);
+        var bottom: f32 = mix(pixel(x, y + 1), pixel(x + 1, y + 1), t.x);
+        value = mix(top, bottom, t.y);
     }
 
-    return vec4<f32>(1.0, 1.0, 1.0, 1.0);
+    return vec4<f32>(mix(params.palette[0].rgb, params.palette[1].rgb, value), 1.0);
 }
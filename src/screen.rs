@@ -1,6 +1,12 @@
+use crate::error::Error;
+use crate::ProgramOptions;
 use chip_8_core::*;
+use chip_8_desktop::session::panic_message;
 use ggez::graphics;
 use std::mem::{self, size_of};
+use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
 use wgpu::util::DeviceExt;
 
 // screen triangle
@@ -12,24 +18,445 @@ const VERTEX_LIST: [f32; 9] = [
      3.0, -1.0, 0.0,
 ];
 
-// "pixel" size on output window
-pub const SCREEN_SCALE_FACTOR: usize = 10;
+// default "pixel" size on output window
+pub const DEFAULT_SCALE_FACTOR: u32 = 10;
+
+/* how often `--watch-shader` looks at the file */
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/* how the emulated screen is fitted into a window whose size isn't an exact multiple of it */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Scaling {
+    #[default]
+    Integer, // crisp pixels, letterboxed
+    Smooth, // fills the window, bilinear filtering
+}
+
+impl Scaling {
+    pub fn parse(arg: &str) -> Option<Self> {
+        match arg {
+            "integer" => Some(Self::Integer),
+            "smooth" => Some(Self::Smooth),
+            _ => None,
+        }
+    }
+}
+
+/* `--rotate` and `--flip`: the screen is turned clockwise, then mirrored as shown */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Orientation {
+    pub quarter_turns: u8, // clockwise, 0 to 3
+    pub mirror: bool,      // left and right swapped
+    pub flip: bool,        // upside down
+}
+
+impl Orientation {
+    /* "90", "180"... */
+    pub fn parse_rotation(arg: &str) -> Option<u8> {
+        match arg {
+            "0" => Some(0),
+            "90" => Some(1),
+            "180" => Some(2),
+            "270" => Some(3),
+            _ => None,
+        }
+    }
+
+    /* size of the screen as shown, in emulated pixels */
+    pub fn size(self) -> (usize, usize) {
+        if self.quarter_turns % 2 == 1 {
+            (SCREEN_HEIGHT, SCREEN_WIDTH)
+        } else {
+            (SCREEN_WIDTH, SCREEN_HEIGHT)
+        }
+    }
+
+    /* emulated pixel shown at `x`, `y` (the shader does the same on continuous coordinates) */
+    fn source(self, x: usize, y: usize) -> (usize, usize) {
+        let (width, height) = self.size();
+        let x = if self.mirror { width - 1 - x } else { x };
+        let y = if self.flip { height - 1 - y } else { y };
+
+        match self.quarter_turns % 4 {
+            1 => (y, SCREEN_HEIGHT - 1 - x),
+            2 => (SCREEN_WIDTH - 1 - x, SCREEN_HEIGHT - 1 - y),
+            3 => (SCREEN_WIDTH - 1 - y, x),
+            _ => (x, y),
+        }
+    }
+
+    /* `orientation` in the shader parameters */
+    fn bits(self) -> u32 {
+        (self.quarter_turns % 4) as u32 | (self.mirror as u32) << 2 | (self.flip as u32) << 3
+    }
+}
+
+/* Part of the screen shown, magnified to fill it (accessibility zoom), in emulated pixels of the
+ * screen as shown */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct View {
+    pub level: f32, // 1: the whole screen
+    pub center: [f32; 2],
+}
+
+impl View {
+    pub fn whole(orientation: Orientation) -> Self {
+        let (width, height) = orientation.size();
+        Self {
+            level: 1.0,
+            center: [width as f32 / 2.0, height as f32 / 2.0],
+        }
+    }
+}
+
+/* Colors of the pixel values, as "#RRGGBB": 0 is off and 1 is on. XO-CHIP's second bit plane would
+ * add 2 (second plane) and 3 (both planes), which the core doesn't support yet; the remaining
+ * entries are there for future extensions. The defaults are Octo's.
+ */
+pub const PALETTE_SIZE: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette(pub [[u8; 3]; PALETTE_SIZE]);
+
+impl Default for Palette {
+    fn default() -> Self {
+        let mut res = [[0x00; 3]; PALETTE_SIZE];
+        res[1] = [0xFF; 3];
+        res[2] = [0xFF, 0x66, 0x00];
+        res[3] = [0x66, 0x22, 0x00];
+        Self(res)
+    }
+}
+
+pub fn parse_color(arg: &str) -> Option<[u8; 3]> {
+    let hex = arg.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    let [_, r, g, b] = value.to_be_bytes();
+    Some([r, g, b])
+}
+
+/* Accessible palettes, for entries 0 to 3: the XO-CHIP colors are kept apart as well. The
+ * color-blind safe ones are drawn from the Okabe-Ito set, and avoid telling pixels apart by red
+ * and green alone.
+ */
+pub const PRESETS: [(&str, [[u8; 3]; 4]); 3] = [
+    (
+        "high-contrast",
+        [[0x00; 3], [0xFF; 3], [0xFF, 0xFF, 0x00], [0x00, 0xFF, 0xFF]],
+    ),
+    (
+        "deuteranopia",
+        [[0x00; 3], [0xF0, 0xE4, 0x42], [0x00, 0x72, 0xB2], [0xFF; 3]],
+    ),
+    (
+        "protanopia",
+        [[0x00; 3], [0x56, 0xB4, 0xE9], [0xE6, 0x9F, 0x00], [0xFF; 3]],
+    ),
+];
+
+/* `--palette` value: comma-separated colors replacing the first entries of the palette, or the
+ * name of one of the `PRESETS` */
+pub fn parse_palette(arg: &str) -> Option<Vec<[u8; 3]>> {
+    if let Some((_, colors)) = PRESETS.iter().find(|(name, _)| *name == arg) {
+        return Some(colors.to_vec());
+    }
+
+    let colors: Vec<_> = arg
+        .split(',')
+        .map(|c| parse_color(c.trim()))
+        .collect::<Option<_>>()?;
+    Some(colors).filter(|colors| (1..=PALETTE_SIZE).contains(&colors.len()))
+}
+
+impl Palette {
+    pub fn set(&mut self, colors: &[[u8; 3]]) {
+        self.0[..colors.len()].copy_from_slice(colors);
+    }
+}
+
+/* `--renderer` value */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Renderer {
+    #[default]
+    Auto, // the GPU pipeline, unless it can't be created
+    Gpu,
+    Software,
+}
+
+impl Renderer {
+    pub fn parse(arg: &str) -> Option<Self> {
+        match arg {
+            "auto" => Some(Self::Auto),
+            "gpu" => Some(Self::Gpu),
+            "software" => Some(Self::Software),
+            _ => None,
+        }
+    }
+}
 
 pub struct Screen {
+    output: Output,
+    scaling: Scaling,
+    orientation: Orientation,
+    view: Mutex<View>,
+    palette: Mutex<Palette>,                // can be switched at runtime
+    border: graphics::Color,                // around the emulated screen
+    background: Option<graphics::Image>,    // drawn over the border color, stretched to the window
+    generation: AtomicU64,                  // of the framebuffer last uploaded
+    shader_file: Option<Mutex<ShaderFile>>, // in place of the built-in shader
+}
+
+enum Output {
+    Pipeline(Mutex<Box<Pipeline>>), // replaced when the shader file is loaded or changes
+    // for broken GPU drivers: the framebuffer as a plain texture, None until the first frame
+    Software(Mutex<Option<graphics::Image>>),
+}
+
+/* `--shader`: the pipeline starts with the built-in shader, and is rebuilt with the one in the
+ * file once the window is up; while it doesn't build, the previous version stays in use. With
+ * `--watch-shader` this happens again whenever the file changes.
+ */
+struct ShaderFile {
+    path: String,
+    watch: bool,
+    modified: Option<Option<SystemTime>>, // of the version last tried, None before the first try
+    checked: Option<Instant>,
+}
+
+/* custom pipeline scaling the framebuffer in a fragment shader */
+struct Pipeline {
     verts: wgpu::Buffer,
     inds: wgpu::Buffer,
     pixel_buffer: wgpu::Buffer,
+    params: wgpu::Buffer,
+    started: Instant, // `time` in the shader parameters
     pipeline: wgpu::RenderPipeline,
     bind_group: wgpu::BindGroup,
 }
 
 impl Screen {
-    pub fn new(ctx: &ggez::Context) -> ggez::GameResult<Screen> {
-        let shader = ctx
-            .gfx
-            .wgpu()
-            .device
-            .create_shader_module(wgpu::include_wgsl!("scale_pixels.wgsl"));
+    pub fn new(ctx: &ggez::Context, options: &ProgramOptions) -> Result<Screen, Error> {
+        let (scaling, orientation, palette) =
+            (options.scaling, options.orientation, options.palette);
+
+        let shader = || wgpu::include_wgsl!("scale_pixels.wgsl");
+        let output = match options.renderer {
+            Renderer::Gpu => Output::Pipeline(Mutex::new(Box::new(
+                Pipeline::new(ctx, scaling, orientation, &palette, shader())
+                    .map_err(|e| Error::Gpu(e.to_string()))?,
+            ))),
+            Renderer::Software => {
+                tracing::info!("software rendering");
+                Output::Software(Mutex::new(None))
+            }
+            Renderer::Auto => match try_pipeline(ctx, scaling, orientation, &palette, shader()) {
+                Ok(pipeline) => Output::Pipeline(Mutex::new(Box::new(pipeline))),
+                Err(_) => {
+                    tracing::warn!("cannot create the screen pipeline, using software rendering");
+                    Output::Software(Mutex::new(None))
+                }
+            },
+        };
+
+        // the image is decoded from memory, ggez only loads paths from its resource directories
+        let background = match &options.background {
+            Some(path) => {
+                let invalid = |reason: String| Error::Background(path.clone(), reason);
+                let bytes = std::fs::read(path).map_err(|e| invalid(e.to_string()))?;
+                Some(graphics::Image::from_bytes(ctx, &bytes).map_err(|e| invalid(e.to_string()))?)
+            }
+            None => None,
+        };
+
+        let [r, g, b] = options.border;
+        Ok(Screen {
+            output,
+            scaling,
+            orientation,
+            view: Mutex::new(View::whole(orientation)),
+            palette: Mutex::new(palette),
+            border: graphics::Color::from_rgb(r, g, b),
+            background,
+            generation: AtomicU64::new(u64::MAX),
+            shader_file: options.shader.as_ref().map(|path| {
+                Mutex::new(ShaderFile {
+                    path: path.clone(),
+                    watch: options.watch_shader,
+                    modified: None,
+                    checked: None,
+                })
+            }),
+        })
+    }
+
+    /* `--shader`: builds the pipeline with the shader file if it wasn't tried yet or, with
+     * `--watch-shader`, if it changed since it was last looked at; returns the outcome if it did
+     * (the error is meant to be shown, it's already logged) */
+    pub fn reload_shader(&self, ctx: &ggez::Context) -> Option<Result<(), String>> {
+        let (file, pipeline) = match (&self.shader_file, &self.output) {
+            (Some(file), Output::Pipeline(pipeline)) => (file, pipeline),
+            _ => return None,
+        };
+        let mut file = file.lock().ok()?;
+        let tried = file.modified.is_some();
+        if (tried && !file.watch)
+            || file
+                .checked
+                .is_some_and(|checked| checked.elapsed() < WATCH_INTERVAL)
+        {
+            return None;
+        }
+        file.checked = Some(Instant::now());
+
+        let modified = std::fs::metadata(&file.path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        if file.modified == Some(modified) {
+            return None;
+        }
+        file.modified = Some(modified);
+
+        let path = &file.path;
+        let res = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read {}: {}", path, e))
+            .and_then(|source| {
+                let shader = wgpu::ShaderModuleDescriptor {
+                    label: Some(path),
+                    source: wgpu::ShaderSource::Wgsl(source.into()),
+                };
+                let palette = *self.palette.lock().map_err(|e| e.to_string())?;
+                try_pipeline(ctx, self.scaling, self.orientation, &palette, shader)
+            });
+
+        Some(match res {
+            Ok(new) => {
+                new.set_view(ctx, *self.view.lock().ok()?);
+                **pipeline.lock().ok()? = new;
+                tracing::info!(path, "shader loaded");
+                Ok(())
+            }
+            Err(e) => {
+                tracing::warn!(path, "cannot build the shader: {}", e);
+                Err(e)
+            }
+        })
+    }
+
+    pub fn set_view(&self, ctx: &ggez::Context, view: View) -> ggez::GameResult {
+        *self.view.lock().map_err(Error::sync("screen"))? = view;
+        if let Output::Pipeline(pipeline) = &self.output {
+            let pipeline = pipeline.lock().map_err(Error::sync("screen"))?;
+            pipeline.set_view(ctx, view);
+        }
+
+        Ok(())
+    }
+
+    /* the screen has to be repainted for it to show */
+    pub fn set_palette(&self, ctx: &ggez::Context, palette: Palette) -> ggez::GameResult {
+        *self.palette.lock().map_err(Error::sync("screen"))? = palette;
+        match &self.output {
+            Output::Pipeline(pipeline) => {
+                let pipeline = pipeline.lock().map_err(Error::sync("screen"))?;
+                ctx.gfx.wgpu().queue.write_buffer(
+                    &pipeline.params,
+                    PALETTE_OFFSET,
+                    &palette_params(&palette),
+                );
+            }
+            // rebuilt on the next draw
+            Output::Software(image) => *image.lock().map_err(Error::sync("screen"))? = None,
+        }
+
+        Ok(())
+    }
+
+    /* to be called when the window is resized */
+    pub fn resize(&self, ctx: &ggez::Context, width: f32, height: f32) {
+        tracing::debug!(width, height, "screen resized");
+        // the software renderer lays the screen out on every draw
+        if let Output::Pipeline(pipeline) = &self.output {
+            let pipeline = match pipeline.lock() {
+                Ok(pipeline) => pipeline,
+                Err(_) => return,
+            };
+            ctx.gfx.wgpu().queue.write_buffer(
+                &pipeline.params,
+                0,
+                &layout_params(self.scaling, self.orientation, width, height),
+            );
+        }
+    }
+
+    /* The frame drawn last is kept by ggez, so nothing needs to be done if the framebuffer didn't
+     * change since then, unless `repaint` is set (something else was drawn on top of it).
+     */
+    pub fn draw(
+        &self,
+        ctx: &mut ggez::Context,
+        fb: &FrameBuffer,
+        generation: u64,
+        repaint: bool,
+    ) -> ggez::GameResult {
+        let changed = self.generation.swap(generation, Relaxed) != generation;
+        // custom shaders may be animated, they're redrawn on every frame
+        if !changed && !repaint && self.shader_file.is_none() {
+            return Ok(());
+        }
+
+        self.draw_decoration(ctx)?;
+        match &self.output {
+            Output::Pipeline(pipeline) => {
+                let pipeline = pipeline.lock().map_err(Error::sync("screen"))?;
+                pipeline.draw(ctx, fb, changed)
+            }
+            Output::Software(image) => {
+                let mut image = image.lock().map_err(Error::sync("screen"))?;
+                if changed || image.is_none() {
+                    let palette = self.palette.lock().map_err(Error::sync("screen"))?;
+                    *image = Some(framebuffer_image(ctx, fb, self.orientation, &palette));
+                }
+                let view = *self.view.lock().map_err(Error::sync("screen"))?;
+                draw_image(
+                    ctx,
+                    image.as_ref().unwrap(),
+                    self.scaling,
+                    self.orientation,
+                    view,
+                )
+            }
+        }
+    }
+
+    /* first pass, clearing the frame: the emulated screen is then drawn on top of it */
+    fn draw_decoration(&self, ctx: &mut ggez::Context) -> ggez::GameResult {
+        let mut canvas = graphics::Canvas::from_frame(ctx, self.border);
+        if let Some(background) = &self.background {
+            let (width, height) = ctx.gfx.drawable_size();
+            canvas.draw(
+                background,
+                graphics::DrawParam::default().scale([
+                    width / background.width() as f32,
+                    height / background.height() as f32,
+                ]),
+            );
+        }
+        canvas.finish(ctx)
+    }
+}
+
+impl Pipeline {
+    fn new(
+        ctx: &ggez::Context,
+        scaling: Scaling,
+        orientation: Orientation,
+        palette: &Palette,
+        shader: wgpu::ShaderModuleDescriptor,
+    ) -> ggez::GameResult<Pipeline> {
+        let shader = ctx.gfx.wgpu().device.create_shader_module(shader);
 
         let verts = ctx
             .gfx
@@ -108,15 +535,16 @@ impl Screen {
                     usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
                 });
 
-        let push_scale =
-            ctx.gfx
-                .wgpu()
-                .device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: None,
-                    contents: &u32::to_ne_bytes(SCREEN_SCALE_FACTOR as u32),
-                    usage: wgpu::BufferUsages::UNIFORM,
-                });
+        let (width, height) = ctx.gfx.drawable_size();
+        let params = ctx
+            .gfx
+            .wgpu()
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: &shader_params(scaling, orientation, palette, width, height),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
 
         let bind_group = ctx
             .gfx
@@ -129,7 +557,7 @@ impl Screen {
                     wgpu::BindGroupEntry {
                         binding: 0,
                         resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                            buffer: &push_scale,
+                            buffer: &params,
                             offset: 0,
                             size: None,
                         }),
@@ -145,20 +573,46 @@ impl Screen {
                 ],
             });
 
-        Ok(Screen {
+        tracing::info!(
+            surface_format = ?ctx.gfx.surface_format(),
+            ?scaling,
+            "screen pipeline created"
+        );
+
+        Ok(Pipeline {
             verts,
             inds,
             pixel_buffer,
+            params,
+            started: Instant::now(),
             pipeline,
             bind_group,
         })
     }
 
-    pub fn draw(&self, ctx: &mut ggez::Context, fb: &FrameBuffer) -> ggez::GameResult {
+    fn set_view(&self, ctx: &ggez::Context, view: View) {
+        let mut params = [0; 12];
+        params[0..4].copy_from_slice(&view.center[0].to_ne_bytes());
+        params[4..8].copy_from_slice(&view.center[1].to_ne_bytes());
+        params[8..12].copy_from_slice(&view.level.to_ne_bytes());
+        ctx.gfx
+            .wgpu()
+            .queue
+            .write_buffer(&self.params, VIEW_OFFSET, &params);
+    }
+
+    fn draw(&self, ctx: &mut ggez::Context, fb: &FrameBuffer, changed: bool) -> ggez::GameResult {
+        if changed {
+            ctx.gfx
+                .wgpu()
+                .queue
+                .write_buffer(&self.pixel_buffer, 0, &fix_u32_endianness(fb));
+        }
+        let time = self.started.elapsed().as_secs_f32();
         ctx.gfx
             .wgpu()
             .queue
-            .write_buffer(&self.pixel_buffer, 0, &fix_u32_endianness(fb));
+            .write_buffer(&self.params, TIME_OFFSET, &time.to_ne_bytes());
 
         let frame = ctx.gfx.frame().clone();
         let cmd = ctx.gfx.commands().unwrap();
@@ -169,10 +623,8 @@ impl Screen {
                 view: frame.wgpu().1,
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(
-                        graphics::LinearColor::from(graphics::Color::new(0.5, 0.4, 0.2, 1.0))
-                            .into(),
-                    ),
+                    // the border is already there, the shader leaves it alone
+                    load: wgpu::LoadOp::Load,
                     store: true,
                 },
             })],
@@ -189,6 +641,215 @@ impl Screen {
     }
 }
 
+/* wgpu reports validation errors (e.g. a shader the driver chokes on) by panicking */
+fn try_pipeline(
+    ctx: &ggez::Context,
+    scaling: Scaling,
+    orientation: Orientation,
+    palette: &Palette,
+    shader: wgpu::ShaderModuleDescriptor,
+) -> Result<Pipeline, String> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        Pipeline::new(ctx, scaling, orientation, palette, shader)
+    }))
+    .unwrap_or_else(|payload| Err(ggez::GameError::RenderError(panic_message(&*payload))))
+    .map_err(|e| e.to_string())
+}
+
+/* top-left corner and pixel size of the emulated screen in a window of the given size (in
+ * physical pixels) */
+fn layout(scaling: Scaling, orientation: Orientation, width: f32, height: f32) -> (f32, f32, f32) {
+    let (screen_width, screen_height) = orientation.size();
+    let fit = (width / screen_width as f32).min(height / screen_height as f32);
+    let scale = match scaling {
+        Scaling::Integer => fit.floor().max(1.0),
+        Scaling::Smooth => fit,
+    };
+
+    // centered, on whole pixels
+    let offset_x = ((width - screen_width as f32 * scale) / 2.0).floor();
+    let offset_y = ((height - screen_height as f32 * scale) / 2.0).floor();
+
+    (offset_x, offset_y, scale)
+}
+
+/* position of `time` in `Params`, after the part set by `layout_params` */
+const TIME_OFFSET: u64 = 24;
+/* position of `zoom_center` and `zoom`, after `orientation` */
+const VIEW_OFFSET: u64 = 32;
+/* position of `palette`, the last member */
+const PALETTE_OFFSET: u64 = 48;
+
+/* `Params` uniform of the shader, the interface of custom shaders (see scale_pixels.wgsl) */
+fn shader_params(
+    scaling: Scaling,
+    orientation: Orientation,
+    palette: &Palette,
+    width: f32,
+    height: f32,
+) -> Vec<u8> {
+    let mut res = layout_params(scaling, orientation, width, height).to_vec();
+    res.extend_from_slice(&0f32.to_ne_bytes()); // time
+    res.extend_from_slice(&orientation.bits().to_ne_bytes());
+    let view = View::whole(orientation);
+    for value in [view.center[0], view.center[1], view.level] {
+        res.extend_from_slice(&value.to_ne_bytes());
+    }
+    res.extend_from_slice(&[0; 4]); // padding, the palette is aligned to 16 bytes
+    res.extend_from_slice(&palette_params(palette));
+
+    res
+}
+
+fn palette_params(palette: &Palette) -> Vec<u8> {
+    let mut res = Vec::with_capacity(PALETTE_SIZE * 16);
+
+    // the shader works in linear color space, hex colors are sRGB
+    for [r, g, b] in palette.0 {
+        let color = graphics::LinearColor::from(graphics::Color::from_rgb(r, g, b));
+        for channel in [color.r, color.g, color.b, color.a] {
+            res.extend_from_slice(&channel.to_ne_bytes());
+        }
+    }
+
+    res
+}
+
+/* the part of `Params` depending on the window size */
+fn layout_params(
+    scaling: Scaling,
+    orientation: Orientation,
+    width: f32,
+    height: f32,
+) -> [u8; TIME_OFFSET as usize] {
+    let (offset_x, offset_y, scale) = layout(scaling, orientation, width, height);
+
+    let mut res = [0; TIME_OFFSET as usize];
+    res[0..4].copy_from_slice(&offset_x.to_ne_bytes());
+    res[4..8].copy_from_slice(&offset_y.to_ne_bytes());
+    res[8..12].copy_from_slice(&scale.to_ne_bytes());
+    res[12..16].copy_from_slice(&u32::to_ne_bytes((scaling == Scaling::Smooth) as u32));
+    res[16..20].copy_from_slice(&width.to_ne_bytes());
+    res[20..24].copy_from_slice(&height.to_ne_bytes());
+
+    res
+}
+
+/* one texel per emulated pixel, turned as shown */
+fn framebuffer_image(
+    ctx: &ggez::Context,
+    fb: &FrameBuffer,
+    orientation: Orientation,
+    palette: &Palette,
+) -> graphics::Image {
+    let (width, height) = orientation.size();
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    for y in 0..height {
+        for x in 0..width {
+            let (x, y) = orientation.source(x, y);
+            rgba.extend_from_slice(&palette.0[is_pixel_on(fb, x, y) as usize]);
+            rgba.push(0xFF);
+        }
+    }
+
+    graphics::Image::from_pixels(
+        ctx,
+        &rgba,
+        graphics::ImageFormat::Rgba8UnormSrgb,
+        width as u32,
+        height as u32,
+    )
+}
+
+/* software counterpart of the pipeline: the same layout, filtered by the sampler */
+fn draw_image(
+    ctx: &mut ggez::Context,
+    image: &graphics::Image,
+    scaling: Scaling,
+    orientation: Orientation,
+    view: View,
+) -> ggez::GameResult {
+    let (width, height) = ctx.gfx.drawable_size();
+    let (offset_x, offset_y, scale) = layout(scaling, orientation, width, height);
+
+    // the part of the texture in view, in texture coordinates
+    let (screen_width, screen_height) = orientation.size();
+    let part = graphics::Rect::new(
+        view.center[0] / screen_width as f32 - 0.5 / view.level,
+        view.center[1] / screen_height as f32 - 0.5 / view.level,
+        1.0 / view.level,
+        1.0 / view.level,
+    );
+
+    let mut canvas = graphics::Canvas::from_frame(ctx, None);
+    canvas.set_sampler(match scaling {
+        Scaling::Integer => graphics::Sampler::nearest_clamp(),
+        Scaling::Smooth => graphics::Sampler::linear_clamp(),
+    });
+    canvas.draw(
+        image,
+        graphics::DrawParam::default()
+            .src(part)
+            .dest([offset_x, offset_y])
+            .scale([scale * view.level, scale * view.level]),
+    );
+
+    canvas.finish(ctx)
+}
+
+/* `--gpu-backend` value; with `auto` wgpu picks the first API that works, falling back from
+ * Vulkan, Metal and DX12 to DX11 and OpenGL
+ */
+pub fn parse_backend(arg: &str) -> Option<ggez::conf::Backend> {
+    use ggez::conf::Backend;
+
+    match arg {
+        "auto" => Some(Backend::All),
+        "vulkan" => Some(Backend::Vulkan),
+        "dx12" => Some(Backend::Dx12),
+        "dx11" => Some(Backend::Dx11),
+        "metal" => Some(Backend::Metal),
+        "gl" => Some(Backend::Gl),
+        _ => None,
+    }
+}
+
+/* window icon: "C8" in the built-in CHIP-8 font, on a black background */
+pub fn window_icon() -> Option<ggez::winit::window::Icon> {
+    const SIZE: usize = 32;
+    const PIXEL: usize = 3; // icon pixels per font pixel
+    #[rustfmt::skip]
+    const GLYPHS: [[u8; 5]; 2] = [
+        [0xF0, 0x80, 0x80, 0x80, 0xF0], // C
+        [0xF0, 0x90, 0xF0, 0x90, 0xF0], // 8
+    ];
+
+    let mut rgba = vec![0; SIZE * SIZE * 4];
+    for (i, glyph) in GLYPHS.iter().enumerate() {
+        for (row, bits) in glyph.iter().enumerate() {
+            for col in 0..4 {
+                if bits & (0x80 >> col) == 0 {
+                    continue;
+                }
+
+                // glyphs are 4x5 font pixels, one font pixel apart, centered
+                let x0 = 2 + (i * 5 + col) * PIXEL;
+                let y0 = 8 + row * PIXEL;
+                for y in y0..y0 + PIXEL {
+                    for x in x0..x0 + PIXEL {
+                        rgba[(y * SIZE + x) * 4..][..3].copy_from_slice(&[0xFF; 3]);
+                    }
+                }
+            }
+        }
+    }
+    for alpha in rgba.iter_mut().skip(3).step_by(4) {
+        *alpha = 0xFF;
+    }
+
+    ggez::winit::window::Icon::from_rgba(rgba, SIZE as u32, SIZE as u32).ok()
+}
+
 /* Utility function to correctly reinterpret the u8 FrameBuffer as a buffer of u32 */
 fn fix_u32_endianness(bytes_slice: &FrameBuffer) -> FrameBuffer {
     let mut buffer = [0; size_of::<FrameBuffer>()];
@@ -204,3 +865,9 @@ fn fix_u32_endianness(bytes_slice: &FrameBuffer) -> FrameBuffer {
 
     buffer
 }
+
+/* Utility function to read a single pixel of the FrameBuffer (rows of 8 bytes, MSB on the left) */
+pub fn is_pixel_on(fb: &FrameBuffer, x: usize, y: usize) -> bool {
+    let byte = fb[y * (chip_8_core::SCREEN_WIDTH / 8) + x / 8];
+    byte & (0x80 >> (x % 8)) != 0
+}
@@ -0,0 +1,243 @@
+use chip_8_core::{Chip8, FrameBuffer, IOCallbacks};
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use std::any::Any;
+use std::fmt;
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU64, AtomicU8};
+use std::sync::Mutex;
+
+/* largest program that fits in the 4K address space after the interpreter area (0x000-0x1FF) */
+pub const MAX_ROM_SIZE: usize = 0x1000 - 0x200;
+
+/* machine configuration, the same quirks as the desktop frontend */
+#[derive(Clone, Copy)]
+pub struct Config {
+    pub clip_sprites: bool,
+    pub schip_compatibility: bool,
+    pub clock_speed: u32, // instructions per second of emulated time, sets the rate of the timers
+    pub rng_seed: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            clip_sprites: false,
+            schip_compatibility: false,
+            clock_speed: 500,
+            // fixed, so that repeated runs of the same ROM produce the same output
+            rng_seed: 0xC8,
+        }
+    }
+}
+
+/* The core panicked while executing an instruction (invalid opcode, stack overflow, access out of
+ * memory...); its state can't be trusted afterwards. The core doesn't report the program counter
+ * or the opcode, only the panic message tells what went wrong.
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fault {
+    pub cycle: u64, // instructions executed before the faulty one
+    pub message: String,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "instruction {} failed: {}", self.cycle + 1, self.message)
+    }
+}
+
+/* message of a caught panic, as given to `panic!` */
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match (
+        payload.downcast_ref::<&str>(),
+        payload.downcast_ref::<String>(),
+    ) {
+        (Some(message), _) => message.to_string(),
+        (_, Some(message)) => message.clone(),
+        _ => "unknown cause".to_owned(),
+    }
+}
+
+/* machine state shared with the callbacks */
+struct SessionIo {
+    delay: AtomicU8,
+    sound: AtomicU8,
+    keys: AtomicU16, // held keypad keys, one bit per key
+    waiting: AtomicBool,
+    draws: AtomicU64,
+    rng: Mutex<SmallRng>,
+}
+
+/* Core running standalone (no window, audio device or GPU context), stepped by the caller.
+ * Timers are driven by the number of executed instructions rather than by wall-clock time.
+ *
+ * The core can't suspend an instruction: when Fx0A runs while no key is held and the frontend has
+ * no key to report either (see `with_key_wait`), it completes with key 0, and `is_waiting_for_key`
+ * reports it until the next step. The core doesn't expose its
+ * registers or memory either, so there is no way to save or restore a session.
+ */
+pub struct Session<'a> {
+    core: Chip8<'a>,
+    io: &'a SessionIo,
+    cycles: u64,
+    cycles_per_timer_tick: u64,
+    fault: Option<Fault>, // the session is over
+}
+
+impl Session<'_> {
+    /* panics if the core does, see `try_step` */
+    pub fn step(&mut self) {
+        self.io.waiting.store(false, Relaxed);
+        self.core.execute_next_instruction();
+        self.tick();
+    }
+
+    /* Same as `step`, returning the panics of the core as errors instead. Once an instruction
+     * failed, every following step fails the same way without touching the core.
+     */
+    pub fn try_step(&mut self) -> Result<(), Fault> {
+        if let Some(fault) = &self.fault {
+            return Err(fault.clone());
+        }
+
+        self.io.waiting.store(false, Relaxed);
+        let core = &mut self.core;
+        if let Err(payload) = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            core.execute_next_instruction()
+        })) {
+            let fault = Fault {
+                cycle: self.cycles,
+                message: panic_message(&*payload),
+            };
+            self.fault = Some(fault.clone());
+            return Err(fault);
+        }
+        self.tick();
+
+        Ok(())
+    }
+
+    fn tick(&mut self) {
+        self.cycles += 1;
+
+        if self.cycles % self.cycles_per_timer_tick == 0 {
+            let _ = self
+                .io
+                .delay
+                .fetch_update(Relaxed, Relaxed, |x| x.checked_sub(1));
+            let _ = self
+                .io
+                .sound
+                .fetch_update(Relaxed, Relaxed, |x| x.checked_sub(1));
+        }
+    }
+
+    pub fn press_key(&self, key: u8) {
+        self.io.keys.fetch_or(1 << (key & 0xF), Relaxed);
+    }
+
+    pub fn release_key(&self, key: u8) {
+        self.io.keys.fetch_and(!(1 << (key & 0xF)), Relaxed);
+    }
+
+    /* the last instruction was an Fx0A that got no key */
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.io.waiting.load(Relaxed)
+    }
+
+    /* the failed instruction, once `try_step` returned it */
+    pub fn fault(&self) -> Option<&Fault> {
+        self.fault.as_ref()
+    }
+
+    pub fn framebuffer(&self) -> &FrameBuffer {
+        self.core.fb_ref()
+    }
+
+    /* instructions executed so far */
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /* draw instructions executed so far */
+    pub fn draws(&self) -> u64 {
+        self.io.draws.load(Relaxed)
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.io.delay.load(Relaxed)
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.io.sound.load(Relaxed)
+    }
+}
+
+/* the core borrows its callbacks, so they're kept on this stack frame while `f` runs */
+pub fn with_session<R>(program: &[u8], config: &Config, f: impl FnOnce(&mut Session) -> R) -> R {
+    with_key_wait(program, config, || None, f)
+}
+
+/* Same as `with_session`, for interactive frontends: `wait_for_key` is called when Fx0A finds no
+ * key held, and blocks until a key is pressed. The key it returns must then be pressed on the
+ * session as usual; `None` gives up on the wait.
+ */
+pub fn with_key_wait<R>(
+    program: &[u8],
+    config: &Config,
+    wait_for_key: impl Fn() -> Option<u8> + Send + Sync,
+    f: impl FnOnce(&mut Session) -> R,
+) -> R {
+    let io = SessionIo {
+        delay: AtomicU8::new(0),
+        sound: AtomicU8::new(0),
+        keys: AtomicU16::new(0),
+        waiting: AtomicBool::new(false),
+        draws: AtomicU64::new(0),
+        rng: Mutex::new(SmallRng::seed_from_u64(config.rng_seed)),
+    };
+
+    let sound_setter = |x: u8| io.sound.store(x, Relaxed);
+    let time_setter = |x: u8| io.delay.store(x, Relaxed);
+    let time_getter = || io.delay.load(Relaxed);
+    let is_pressed = |x: u8| io.keys.load(Relaxed) & (1 << (x & 0xF)) != 0;
+    // the lowest held key, if any
+    let wait_for_key = || match io.keys.load(Relaxed) {
+        0 => wait_for_key().unwrap_or_else(|| {
+            io.waiting.store(true, Relaxed);
+            0
+        }),
+        keys => keys.trailing_zeros() as u8,
+    };
+    let next_rand = || io.rng.lock().unwrap().gen::<u8>();
+    let draw_signal = || {
+        io.draws.fetch_add(1, Relaxed);
+    };
+
+    let callbacks = IOCallbacks {
+        sound_setter: &sound_setter,
+        time_setter: &time_setter,
+        time_getter: &time_getter,
+        is_pressed: &is_pressed,
+        wait_for_key: &wait_for_key,
+        rng: &next_rand,
+        draw_signal: &draw_signal,
+    };
+
+    let mut session = Session {
+        core: Chip8::new(
+            program,
+            callbacks,
+            config.clip_sprites,
+            config.schip_compatibility,
+        ),
+        io: &io,
+        cycles: 0,
+        fault: None,
+        // 60 Hz timers, measured in emulated time
+        cycles_per_timer_tick: (config.clock_speed as u64 / 60).max(1),
+    };
+
+    f(&mut session)
+}
@@ -0,0 +1,170 @@
+use crate::error::Error;
+use crate::info;
+use crate::tui::Terminal;
+use crate::variant::PROGRAM_START;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::{cursor, queue, style, terminal};
+use std::io::Write;
+
+/* addressable memory, sprites are read up to its end */
+const MEMORY_SIZE: usize = 0x1000;
+
+const HELP: &str = "←→ byte  ↑↓ sprite  PgUp/PgDn page  +- height  s 16x16  q quit";
+
+/* where the sprites are read from, and their format */
+#[derive(Clone, Copy)]
+struct View {
+    address: usize,
+    height: usize, // rows of the 8-pixel-wide sprites, as in DXYN
+    large: bool,   // SCHIP 16x16 sprites (DXY0), two bytes per row
+}
+
+impl View {
+    fn size(self) -> (usize, usize) {
+        if self.large {
+            (16, 16)
+        } else {
+            (8, self.height)
+        }
+    }
+
+    /* bytes taken by a sprite */
+    fn len(self) -> usize {
+        let (width, height) = self.size();
+        width / 8 * height
+    }
+
+    fn pixel(self, memory: &[u8], address: usize, x: usize, y: usize) -> bool {
+        let (width, height) = self.size();
+        y < height
+            && memory
+                .get(address + y * width / 8 + x / 8)
+                .is_some_and(|byte| byte & (0x80 >> (x % 8)) != 0)
+    }
+
+    /* moves by `bytes`, staying within the program memory */
+    fn scroll(&mut self, bytes: isize) {
+        self.address = self
+            .address
+            .saturating_add_signed(bytes)
+            .clamp(PROGRAM_START, MEMORY_SIZE - 1);
+    }
+}
+
+/* `sprites <rom> [--entry <name>]` entry point: browses the ROM as sprites in the terminal, to
+ * find its graphics; returns the process exit status */
+pub fn run(args: &[String]) -> i32 {
+    let (_, rom) = match info::load_rom("sprites", args) {
+        Some(res) => res,
+        None => return 1,
+    };
+
+    // the ROM as loaded in memory, where the addresses of the program point to
+    let mut memory = vec![0; MEMORY_SIZE];
+    let len = rom.program.len().min(MEMORY_SIZE - PROGRAM_START);
+    memory[PROGRAM_START..PROGRAM_START + len].copy_from_slice(&rom.program[..len]);
+
+    match browse(&memory, &rom.stem) {
+        Ok(()) => 0,
+        Err(e) => {
+            println!("ERROR: {}", e);
+            1
+        }
+    }
+}
+
+fn browse(memory: &[u8], name: &str) -> Result<(), Error> {
+    let terminal_error = |e: std::io::Error| Error::Terminal(e.to_string());
+    let _terminal = Terminal::open().map_err(terminal_error)?;
+    let mut view = View {
+        address: PROGRAM_START,
+        height: 5, // the size of the font glyphs
+        large: false,
+    };
+
+    loop {
+        let shown =
+            draw(&mut std::io::stdout().lock(), memory, view, name).map_err(terminal_error)?;
+
+        let key = match event::read().map_err(terminal_error)? {
+            Event::Key(key) if key.kind != KeyEventKind::Release => key,
+            _ => continue, // resizes are redrawn as well
+        };
+        let sprite = view.len() as isize;
+        match key.code {
+            KeyCode::Left => view.scroll(-1),
+            KeyCode::Right => view.scroll(1),
+            KeyCode::Up => view.scroll(-sprite),
+            KeyCode::Down => view.scroll(sprite),
+            KeyCode::PageUp => view.scroll(-sprite * shown as isize),
+            KeyCode::PageDown => view.scroll(sprite * shown as isize),
+            KeyCode::Home => view.address = PROGRAM_START,
+            KeyCode::Char('+' | '=') => view.height = (view.height + 1).min(15),
+            KeyCode::Char('-') => view.height = (view.height - 1).max(1),
+            KeyCode::Char('s') => view.large = !view.large,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            _ => {}
+        }
+    }
+}
+
+/* consecutive sprites from the address of `view`, as many as fit; returns how many */
+fn draw(stdout: &mut impl Write, memory: &[u8], view: View, name: &str) -> std::io::Result<usize> {
+    let (columns, rows) = terminal::size()?;
+    let (width, height) = view.size();
+    // address label and gap, two pixel rows per text row as in the terminal frontend
+    let cell_width = width.max(5) + 2;
+    let cell_height = height.div_ceil(2) + 2;
+    let across = (columns as usize / cell_width).max(1);
+    let down = (rows.saturating_sub(1) as usize / cell_height).max(1);
+
+    queue!(
+        stdout,
+        terminal::Clear(terminal::ClearType::All),
+        cursor::MoveTo(0, 0)
+    )?;
+
+    let mut shown = 0;
+    for _ in 0..down {
+        let mut lines = vec![String::new(); cell_height];
+        for _ in 0..across {
+            let address = view.address + shown * view.len();
+            if address >= MEMORY_SIZE {
+                break;
+            }
+            shown += 1;
+
+            lines[0] += &format!("{:<1$}", format!("{:03X}", address), cell_width);
+            for (row, line) in lines[1..cell_height - 1].iter_mut().enumerate() {
+                for x in 0..width {
+                    let top = view.pixel(memory, address, x, row * 2);
+                    let bottom = view.pixel(memory, address, x, row * 2 + 1);
+                    line.push(match (top, bottom) {
+                        (true, true) => '█',
+                        (true, false) => '▀',
+                        (false, true) => '▄',
+                        (false, false) => '·',
+                    });
+                }
+                *line += &" ".repeat(cell_width - width);
+            }
+        }
+        for line in lines {
+            queue!(stdout, style::Print(line), cursor::MoveToNextLine(1))?;
+        }
+    }
+
+    let (width, height) = view.size();
+    queue!(
+        stdout,
+        cursor::MoveTo(0, rows.saturating_sub(1)),
+        style::Print(format!(
+            "{} {:03X} {}x{}  {}",
+            name, view.address, width, height, HELP
+        ))
+    )?;
+    stdout.flush()?;
+
+    Ok(shown)
+}
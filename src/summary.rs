@@ -0,0 +1,149 @@
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+use std::time::{Duration, Instant};
+
+/* Calls made by the core, counted for `--stats-out` and the watchdog. The core doesn't expose the opcodes it
+ * decodes, so these are the closest thing to a per-instruction breakdown.
+ */
+#[derive(Default)]
+pub struct IoStats {
+    pub draws: AtomicU64,
+    pub key_polls: AtomicU64, // Ex9E, ExA1
+    pub key_waits: AtomicU64, // Fx0A
+    pub delay_reads: AtomicU64,
+    pub delay_writes: AtomicU64,
+    pub sound_writes: AtomicU64,
+    pub random_numbers: AtomicU64,
+}
+
+impl IoStats {
+    pub fn count(counter: &AtomicU64) {
+        counter.fetch_add(1, Relaxed);
+    }
+
+    /* changes whenever the core calls into the frontend */
+    pub fn total(&self) -> u64 {
+        [
+            &self.draws,
+            &self.key_polls,
+            &self.key_waits,
+            &self.delay_reads,
+            &self.delay_writes,
+            &self.sound_writes,
+            &self.random_numbers,
+        ]
+        .iter()
+        .map(|counter| counter.load(Relaxed))
+        .sum()
+    }
+}
+
+/* session statistics, printed as `key: value` lines on exit with `--summary` */
+pub struct Summary {
+    rom_hash: u64,
+    started: Instant,
+    instructions: u64,
+    dropped_frames: u64, // emulator ticks that overran their time budget
+    errors: Vec<String>,
+}
+
+impl Summary {
+    pub fn new(program: &[u8]) -> Self {
+        Self {
+            rom_hash: crate::headless::fnv1a(program),
+            started: Instant::now(),
+            instructions: 0,
+            dropped_frames: 0,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn count_instructions(&mut self, n: u64) {
+        self.instructions += n;
+    }
+
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    pub fn count_dropped_frames(&mut self, n: u64) {
+        self.dropped_frames += n;
+    }
+
+    pub fn record_error(&mut self, message: &str) {
+        self.errors.push(message.to_owned());
+    }
+
+    pub fn to_text(&self) -> String {
+        let elapsed = self.started.elapsed();
+        let average_hz = per_second(self.instructions, elapsed);
+
+        let mut res = format!(
+            "rom hash: {:016x}\nduration: {:.3}\ninstructions: {}\naverage hz: {:.1}\n\
+             dropped frames: {}\nerrors: {}\n",
+            self.rom_hash,
+            elapsed.as_secs_f64(),
+            self.instructions,
+            average_hz,
+            self.dropped_frames,
+            self.errors.len()
+        );
+        for e in &self.errors {
+            res.push_str(&format!("error: {}\n", e));
+        }
+
+        res
+    }
+
+    /* `--stats-out` report */
+    pub fn to_json(&self, io: &IoStats) -> String {
+        let elapsed = self.started.elapsed();
+        let draws = io.draws.load(Relaxed);
+
+        let json = serde_json::json!({
+            "rom_hash": format!("{:016x}", self.rom_hash),
+            "duration": elapsed.as_secs_f64(),
+            "instructions": self.instructions,
+            "instructions_per_second": per_second(self.instructions, elapsed),
+            "draws": draws,
+            "draws_per_second": per_second(draws, elapsed),
+            "key_polls": io.key_polls.load(Relaxed),
+            "key_waits": io.key_waits.load(Relaxed),
+            "delay_timer_reads": io.delay_reads.load(Relaxed),
+            "delay_timer_writes": io.delay_writes.load(Relaxed),
+            "sound_timer_writes": io.sound_writes.load(Relaxed),
+            "random_numbers": io.random_numbers.load(Relaxed),
+        });
+
+        format!("{:#}\n", json)
+    }
+
+    /* `target` is a file path, or "-" for the standard output */
+    pub fn write(&self, target: &str) {
+        write_report("summary", target, &self.to_text());
+    }
+
+    pub fn write_json(&self, target: &str, io: &IoStats) {
+        write_report("statistics", target, &self.to_json(io));
+    }
+}
+
+fn per_second(count: u64, elapsed: Duration) -> f64 {
+    if elapsed > Duration::ZERO {
+        count as f64 / elapsed.as_secs_f64()
+    } else {
+        0.0
+    }
+}
+
+fn write_report(what: &str, target: &str, text: &str) {
+    let res = if target == "-" {
+        std::io::stdout().write_all(text.as_bytes())
+    } else {
+        std::fs::write(target, text)
+    };
+
+    if let Err(e) = res {
+        crate::error::report(&format!("Cannot write {} to {}: {}", what, target, e));
+    }
+}
@@ -1,20 +1,29 @@
-use ggez::audio::SoundSource;
+use crate::beep::Buzzer;
 use spin_sleep::SpinSleeper;
-use std::sync::atomic::AtomicI16;
+use std::sync::atomic::{AtomicBool, AtomicI16};
+use std::sync::{Arc, Condvar, Mutex};
 
 pub struct DelayTimer {
     value: AtomicI16, // can transiently be -1, in which case it's safe to treat it as == 0
-    sleeper: SpinSleeper,
 }
 
 pub struct SoundTimer {
     value: AtomicI16, // can transiently be -1, in which case it's safe to treat it as == 0
+    sound: Mutex<Buzzer>,
+}
+
+/* single 60 Hz clock decrementing both timers, so that pausing affects them coherently */
+pub struct TimerDriver {
+    delay_timer: Arc<DelayTimer>,
+    sound_timer: Arc<SoundTimer>,
     sleeper: SpinSleeper,
-    sound: ggez::audio::Source,
+    paused: (Condvar, Mutex<bool>), // while paused the thread blocks instead of ticking
+    running: AtomicBool,
 }
 
 pub trait Timer: details::Timer {
-    fn start(&self) -> !;
+    /* called by the TimerDriver at 60 Hz */
+    fn tick(&self);
 
     fn get(&self) -> u8 {
         use std::sync::atomic::Ordering::Relaxed;
@@ -31,20 +40,111 @@ impl DelayTimer {
     pub fn new() -> Self {
         Self {
             value: AtomicI16::new(0),
-            sleeper: spin_sleep::SpinSleeper::default(),
         }
     }
 }
 
 impl SoundTimer {
-    pub fn new(mut sound: ggez::audio::Source) -> Self {
-        // sound is just a waveform that loops
-        sound.set_repeat(true);
-
+    pub fn new(sound: Buzzer) -> Self {
         Self {
             value: AtomicI16::new(0),
-            sleeper: spin_sleep::SpinSleeper::default(),
-            sound,
+            sound: Mutex::new(sound),
+        }
+    }
+
+    /* volume in percent, 0 mutes the buzzer */
+    pub fn set_volume(&self, volume: u8) {
+        if let Ok(mut sound) = self.sound.lock() {
+            sound.set_volume(volume);
+        }
+    }
+
+    /* the buzzer is playing, even if muted */
+    pub fn is_buzzing(&self) -> bool {
+        self.sound.lock().is_ok_and(|sound| sound.is_playing())
+    }
+
+    /* stop the buzzer without touching the timer value, playback resumes on the next tick */
+    fn silence(&self) {
+        if let Ok(mut sound) = self.sound.lock() {
+            sound.pause();
+        }
+    }
+}
+
+impl TimerDriver {
+    pub fn new(delay_timer: Arc<DelayTimer>, sound_timer: Arc<SoundTimer>) -> Self {
+        Self {
+            delay_timer,
+            sound_timer,
+            sleeper: SpinSleeper::default(),
+            paused: (Condvar::new(), Mutex::new(false)),
+            running: AtomicBool::new(true),
+        }
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        let (cond, mtx) = &self.paused;
+
+        // a poisoned lock means the timer thread is gone, nothing to pause
+        if let Ok(mut state) = mtx.lock() {
+            if paused != *state {
+                tracing::debug!(paused, "timers paused");
+            }
+            if paused && !*state {
+                self.sound_timer.silence();
+            }
+            *state = paused;
+        }
+        cond.notify_all();
+    }
+
+    /* make `start()` return within one tick */
+    pub fn stop(&self) {
+        use std::sync::atomic::Ordering::Relaxed;
+
+        self.running.store(false, Relaxed);
+        self.sound_timer.silence();
+
+        // wake the thread up if paused; taking the lock makes sure it isn't between its check of
+        // `running` and the wait, where the notification would be lost
+        let (cond, mtx) = &self.paused;
+        drop(mtx.lock());
+        cond.notify_all();
+    }
+
+    pub fn start(&self) {
+        use std::sync::atomic::Ordering::Relaxed;
+
+        let _span = tracing::info_span!("timers").entered();
+
+        while self.running.load(Relaxed) {
+            use std::time::Duration;
+
+            const TARGET_CLOCK_SPEED: Duration = Duration::new(0, 16_666_667); // 60 Hz
+
+            {
+                let (cond, mtx) = &self.paused;
+                let mut paused = match mtx.lock() {
+                    Ok(paused) => paused,
+                    Err(_) => return,
+                };
+                while *paused && self.running.load(Relaxed) {
+                    paused = match cond.wait(paused) {
+                        Ok(paused) => paused,
+                        Err(_) => return,
+                    };
+                }
+            }
+            if !self.running.load(Relaxed) {
+                return;
+            }
+
+            self.delay_timer.tick();
+            self.sound_timer.tick();
+
+            self.sleeper
+                .sleep_ns(TARGET_CLOCK_SPEED.subsec_nanos() as u64);
         }
     }
 }
@@ -53,7 +153,6 @@ impl SoundTimer {
 mod details {
     pub trait Timer {
         fn get_value(&self) -> &std::sync::atomic::AtomicI16;
-        fn get_sleeper(&self) -> &spin_sleep::SpinSleeper;
     }
 }
 
@@ -61,62 +160,59 @@ impl details::Timer for DelayTimer {
     fn get_value(&self) -> &AtomicI16 {
         &self.value
     }
-
-    fn get_sleeper(&self) -> &spin_sleep::SpinSleeper {
-        &self.sleeper
-    }
 }
 
 impl details::Timer for SoundTimer {
     fn get_value(&self) -> &AtomicI16 {
         &self.value
     }
-
-    fn get_sleeper(&self) -> &spin_sleep::SpinSleeper {
-        &self.sleeper
-    }
 }
 
 impl Timer for DelayTimer {
-    fn start(&self) -> ! {
+    fn tick(&self) {
         use details::Timer;
         use std::sync::atomic::Ordering::Relaxed;
 
-        loop {
-            use std::time::Duration;
-
-            const TARGET_CLOCK_SPEED: Duration = Duration::new(0, 16_666_667); // 60 Hz
-
-            self.get_value().fetch_sub(1, Relaxed);
-            self.get_value().fetch_max(0, Relaxed);
-
-            self.get_sleeper()
-                .sleep_ns(TARGET_CLOCK_SPEED.subsec_nanos() as u64);
-        }
+        self.get_value().fetch_sub(1, Relaxed);
+        self.get_value().fetch_max(0, Relaxed);
     }
 }
 
 impl Timer for SoundTimer {
-    fn start(&self) -> ! {
+    /* start (or stop) the buzzer right away, rather than waiting up to a full tick for the timer
+     * thread to notice; otherwise beeps lasting only a couple of ticks get cut or swallowed */
+    fn set(&self, val: u8) {
         use details::Timer;
         use std::sync::atomic::Ordering::Relaxed;
 
-        loop {
-            use std::time::Duration;
+        let sound = self.sound.lock();
+        self.get_value().store(val as i16, Relaxed);
 
-            const TARGET_CLOCK_SPEED: Duration = Duration::new(0, 16_666_667); // 60 Hz
+        if let Ok(mut sound) = sound {
+            if val > 0 {
+                sound.resume()
+            } else {
+                sound.pause()
+            };
+        }
+    }
 
+    fn tick(&self) {
+        use details::Timer;
+        use std::sync::atomic::Ordering::Relaxed;
+
+        // holding the lock while decrementing, so that the buzzer can't be paused right after
+        // a concurrent `set()` resumed it
+        if let Ok(mut sound) = self.sound.lock() {
             self.get_value().fetch_sub(1, Relaxed);
             let last_val = self.get_value().fetch_max(0, Relaxed);
 
-            if last_val > 1 {
-                self.sound.resume()
+            // stop as soon as the timer hits zero
+            if last_val > 0 {
+                sound.resume()
             } else {
-                self.sound.pause()
+                sound.pause()
             };
-
-            self.get_sleeper()
-                .sleep_ns(TARGET_CLOCK_SPEED.subsec_nanos() as u64);
         }
     }
 }
@@ -0,0 +1,323 @@
+use crate::error::Error;
+use crate::headless;
+use crate::keyboard::{self, HostInput, Inputs, KeyAction, KeyMessage, KeyValue};
+use crate::screen::is_pixel_on;
+use crate::twitch::TwitchPlays;
+use crate::ProgramOptions;
+use chip_8_core::{FrameBuffer, SCREEN_HEIGHT, SCREEN_WIDTH};
+use chip_8_desktop::session::{self, Session};
+use crossterm::event::PushKeyboardEnhancementFlags;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::event::{KeyboardEnhancementFlags, PopKeyboardEnhancementFlags};
+use crossterm::{cursor, execute, queue, style, terminal};
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const FRAME: Duration = Duration::from_micros(16_667);
+
+/* Most terminals only report key presses, repeated while the key is held down; without release
+ * events a key counts as held until its repeats stop. Holds stutter until the repeats kick in.
+ */
+const HOLD: Duration = Duration::from_millis(200);
+
+/* the terminal in raw mode, restored when dropped (also on errors and panics) */
+pub struct Terminal {
+    release_events: bool, // the terminal reports key releases (kitty keyboard protocol)
+}
+
+impl Terminal {
+    pub fn open() -> std::io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(
+            std::io::stdout(),
+            terminal::EnterAlternateScreen,
+            cursor::Hide
+        )?;
+
+        let release_events = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if release_events {
+            execute!(
+                std::io::stdout(),
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+
+        Ok(Self { release_events })
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let mut stdout = std::io::stdout();
+        if self.release_events {
+            let _ = execute!(stdout, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(stdout, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/* Terminal frontend state, shared with the key wait callback: Fx0A blocks inside the core, so the
+ * screen has to be kept up to date and the terminal read from there as well.
+ */
+struct Tui<'a> {
+    options: &'a ProgramOptions,
+    release_events: bool,
+    inputs: Inputs,
+    keyboard: std::sync::mpsc::Sender<KeyMessage>,
+    held: [bool; 16],              // keys held on the terminal
+    expiry: [Option<Instant>; 16], // release of the held keys, if the terminal doesn't report it
+    pending: Vec<KeyMessage>,      // changes picked up while the program waited for a key
+    screen: FrameBuffer,
+    stale: bool, // `screen` isn't on the terminal yet
+    resized: bool,
+    status: String,
+    quit: bool,
+    failure: Option<Error>,
+}
+
+impl Tui<'_> {
+    /* reads the terminal for up to `timeout`, returns the changes of the merged keypad */
+    fn poll(&mut self, timeout: Duration) -> Vec<KeyMessage> {
+        if let Err(e) = self.read_terminal(timeout) {
+            self.fail(e);
+        }
+
+        let now = Instant::now();
+        for key in 0..16 {
+            if self.expiry[key].is_some_and(|expiry| expiry <= now) {
+                self.held[key] = false;
+                self.expiry[key] = None;
+                let _ = self.keyboard.send((key as KeyValue, KeyAction::Released));
+            }
+        }
+
+        let mut res = std::mem::take(&mut self.pending);
+        res.extend(self.inputs.poll());
+        res
+    }
+
+    fn fail(&mut self, e: std::io::Error) {
+        self.failure.get_or_insert(Error::Terminal(e.to_string()));
+        self.quit = true;
+    }
+
+    fn read_terminal(&mut self, timeout: Duration) -> std::io::Result<()> {
+        let deadline = Instant::now() + timeout;
+
+        while event::poll(deadline.saturating_duration_since(Instant::now()))? {
+            match event::read()? {
+                Event::Key(key) => self.on_key(key),
+                Event::Resize(..) => self.resized = true,
+                _ => {}
+            }
+            if self.quit {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn on_key(&mut self, event: KeyEvent) {
+        // raw mode swallows the usual Ctrl+C
+        let interrupted =
+            event.code == KeyCode::Char('c') && event.modifiers.contains(KeyModifiers::CONTROL);
+        if event.code == KeyCode::Esc || interrupted {
+            self.quit = true;
+            return;
+        }
+
+        let key = match event.code {
+            KeyCode::Char(' ') => keyboard::parse_host_key("space"),
+            KeyCode::Enter => keyboard::parse_host_key("enter"),
+            KeyCode::Tab => keyboard::parse_host_key("tab"),
+            KeyCode::Char(c) => keyboard::parse_host_key(&c.to_string()),
+            _ => None,
+        }
+        .and_then(|scancode| keyboard::bound_key(&self.options.key_bindings, scancode));
+        let key = match key {
+            Some(key) => key,
+            None => return,
+        };
+
+        let (held, expiry) = (&mut self.held[key as usize], &mut self.expiry[key as usize]);
+        match event.kind {
+            KeyEventKind::Press | KeyEventKind::Repeat => {
+                if !*held {
+                    *held = true;
+                    let _ = self.keyboard.send((key, KeyAction::Pressed));
+                }
+                if !self.release_events {
+                    *expiry = Some(Instant::now() + HOLD);
+                }
+            }
+            KeyEventKind::Release => {
+                if *held {
+                    *held = false;
+                    let _ = self.keyboard.send((key, KeyAction::Released));
+                }
+            }
+        }
+    }
+
+    /* Fx0A found no key held: block until one is pressed, leaving the changes to the main loop */
+    fn wait_for_key(&mut self) -> Option<KeyValue> {
+        while !self.quit {
+            self.present();
+            let changes = self.poll(FRAME);
+            let pressed = changes
+                .iter()
+                .find(|(_, action)| matches!(action, KeyAction::Pressed))
+                .map(|(key, _)| *key);
+            self.pending.extend(changes);
+            if pressed.is_some() {
+                return pressed;
+            }
+        }
+
+        None
+    }
+
+    fn present(&mut self) {
+        if !self.stale && !self.resized {
+            return;
+        }
+
+        let mut stdout = std::io::stdout().lock();
+        let res = if std::mem::take(&mut self.resized) {
+            queue!(stdout, terminal::Clear(terminal::ClearType::All))
+        } else {
+            Ok(())
+        }
+        .and_then(|_| draw(&mut stdout, &self.screen, &self.status));
+
+        match res {
+            Ok(()) => self.stale = false,
+            Err(e) => self.fail(e),
+        }
+    }
+}
+
+/* two pixel rows per text row, with half-block characters */
+fn draw(stdout: &mut impl Write, fb: &FrameBuffer, status: &str) -> std::io::Result<()> {
+    queue!(stdout, cursor::MoveTo(0, 0))?;
+
+    for y in (0..SCREEN_HEIGHT).step_by(2) {
+        let row: String = (0..SCREEN_WIDTH)
+            .map(
+                |x| match (is_pixel_on(fb, x, y), is_pixel_on(fb, x, y + 1)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                },
+            )
+            .collect();
+        queue!(stdout, style::Print(row), cursor::MoveToNextLine(1))?;
+    }
+    queue!(
+        stdout,
+        style::Print(status),
+        terminal::Clear(terminal::ClearType::UntilNewLine)
+    )?;
+
+    stdout.flush()
+}
+
+/* `--tui` entry point */
+pub fn run(options: &ProgramOptions) -> Result<(), Error> {
+    let (keyboard_source, keyboard) = HostInput::new("keyboard");
+    let mut inputs = Inputs::default();
+    inputs.add(keyboard_source);
+    if let Some(channel) = &options.twitch_plays {
+        inputs.add(TwitchPlays::start(channel)?);
+    }
+    for name in &options.disabled_inputs {
+        inputs.set_enabled(name, false);
+    }
+
+    let terminal = Terminal::open().map_err(|e| Error::Terminal(e.to_string()))?;
+    let tui = Mutex::new(Tui {
+        options,
+        release_events: terminal.release_events,
+        inputs,
+        keyboard,
+        held: [false; 16],
+        expiry: [None; 16],
+        pending: Vec::new(),
+        screen: [0; SCREEN_WIDTH * SCREEN_HEIGHT / 8],
+        stale: true,
+        resized: true,
+        status: format!("{} - Esc to quit", options.rom_name),
+        quit: false,
+        failure: None,
+    });
+
+    let config = headless::session_config(options);
+    let cycles_per_frame = (config.clock_speed as u64 / 60).max(1);
+
+    session::with_key_wait(
+        &options.program,
+        &config,
+        || tui.lock().ok()?.wait_for_key(),
+        |session: &mut Session| -> Result<(), Error> {
+            let mut drawn = 0; // draw count of the latest screen copy
+            let mut buzzing = false;
+            let mut next_frame = Instant::now();
+
+            loop {
+                let changes = {
+                    let mut tui = tui.lock().map_err(Error::sync("terminal"))?;
+                    let changes = tui.poll(next_frame.saturating_duration_since(Instant::now()));
+                    if tui.quit {
+                        return Ok(());
+                    }
+                    changes
+                };
+                next_frame += FRAME;
+
+                for (key, action) in changes {
+                    match action {
+                        KeyAction::Pressed => session.press_key(key),
+                        KeyAction::Released => session.release_key(key),
+                    }
+                }
+
+                for _ in 0..cycles_per_frame {
+                    // returned once the terminal is restored, a panic message would be lost with
+                    // the alternate screen
+                    session
+                        .try_step()
+                        .map_err(|fault| Error::Core(fault.to_string()))?;
+
+                    // copied as it's drawn, for a key wait to show the screen it waits on
+                    if session.draws() != drawn {
+                        drawn = session.draws();
+                        let mut tui = tui.lock().map_err(Error::sync("terminal"))?;
+                        tui.screen = *session.framebuffer();
+                        tui.stale = true;
+                    }
+                    if options.max_cycles > 0 && session.cycles() >= options.max_cycles {
+                        return Ok(());
+                    }
+                }
+
+                // the terminal bell is the only sound there is, rung once per beep
+                let sounding = session.sound_timer() > 0;
+                if sounding && !buzzing {
+                    print!("\x07");
+                }
+                buzzing = sounding;
+
+                tui.lock().map_err(Error::sync("terminal"))?.present();
+            }
+        },
+    )?;
+
+    match tui.into_inner().map_err(Error::sync("terminal"))?.failure {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
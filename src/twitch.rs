@@ -0,0 +1,144 @@
+use crate::error::Error;
+use crate::keyboard::{InputSource, KeyAction, KeyMessage, KeyValue};
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+
+const SERVER: &str = "irc.chat.twitch.tv:6667";
+
+/* Twitch lets anyone read a chat without credentials, under a "justinfan" nickname */
+const ANONYMOUS_NICK: &str = "justinfan31337";
+
+/* each viewer gets one vote per window, the most voted key is then pressed */
+const VOTE_WINDOW: Duration = Duration::from_secs(1);
+
+/* long enough for a program polling the keypad once per frame to notice */
+const TAP_DURATION: Duration = Duration::from_millis(100);
+
+/* `--twitch-plays` mode: chat messages consisting of a single keypad key (e.g. "5") are votes */
+pub struct TwitchPlays {
+    rx: Receiver<KeyMessage>,
+}
+
+impl TwitchPlays {
+    /* connects right away, so that a wrong channel or a missing network is reported on startup */
+    pub fn start(channel: &str) -> Result<Self, Error> {
+        let channel = channel.trim_start_matches('#').to_lowercase();
+        let failed = |e: std::io::Error| Error::Chat(e.to_string());
+
+        let mut stream = TcpStream::connect(SERVER).map_err(failed)?;
+        write!(stream, "NICK {}\r\nJOIN #{}\r\n", ANONYMOUS_NICK, channel).map_err(failed)?;
+        let reader = stream.try_clone().map_err(failed)?;
+        tracing::info!(channel, "listening to chat");
+
+        let (votes_tx, votes) = mpsc::channel();
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || read_chat(reader, stream, votes_tx));
+        std::thread::spawn(move || count_votes(votes, tx));
+
+        Ok(Self { rx })
+    }
+}
+
+impl InputSource for TwitchPlays {
+    fn name(&self) -> &'static str {
+        "chat"
+    }
+
+    fn poll(&mut self) -> Vec<KeyMessage> {
+        self.rx.try_iter().collect()
+    }
+}
+
+/* ":nick!user@host PRIVMSG #channel :text" -> (nick, text) */
+fn parse_privmsg(line: &str) -> Option<(&str, &str)> {
+    let (prefix, rest) = line.strip_prefix(':')?.split_once(' ')?;
+    let nick = prefix.split('!').next()?;
+    let (_, text) = rest.strip_prefix("PRIVMSG ")?.split_once(" :")?;
+    Some((nick, text))
+}
+
+/* a vote is a single hex digit, anything else is just chatting */
+fn parse_vote(text: &str) -> Option<KeyValue> {
+    let mut chars = text.trim().chars();
+    let key = chars.next()?.to_digit(16)?;
+    match chars.next() {
+        Some(_) => None,
+        None => Some(key as KeyValue),
+    }
+}
+
+/* chat thread, until the connection drops */
+fn read_chat(reader: TcpStream, mut writer: TcpStream, votes: Sender<(String, KeyValue)>) {
+    let _span = tracing::info_span!("twitch").entered();
+
+    for line in BufReader::new(reader).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        let line = line.trim_end_matches('\r');
+
+        // the server drops clients not answering its keep-alives
+        if let Some(server) = line.strip_prefix("PING") {
+            if write!(writer, "PONG{}\r\n", server).is_err() {
+                break;
+            }
+            continue;
+        }
+
+        if let Some((nick, key)) =
+            parse_privmsg(line).and_then(|(nick, text)| Some((nick, parse_vote(text)?)))
+        {
+            tracing::trace!(nick, key, "vote");
+            if votes.send((nick.to_owned(), key)).is_err() {
+                return;
+            }
+        }
+    }
+
+    tracing::warn!("chat connection lost, no more votes will be counted");
+}
+
+/* voting thread: tallies a window of votes, then taps the winner */
+fn count_votes(votes: Receiver<(String, KeyValue)>, keys: Sender<KeyMessage>) {
+    let _span = tracing::info_span!("twitch").entered();
+
+    loop {
+        let mut voters = HashSet::new();
+        let mut tally = [0_u32; 16];
+        let deadline = Instant::now() + VOTE_WINDOW;
+
+        loop {
+            match votes.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                // later votes by the same viewer are ignored, so spamming doesn't help
+                Ok((nick, key)) => {
+                    if voters.insert(nick) {
+                        tally[key as usize] += 1;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+
+        // ties go to the lowest key
+        let winner = (0..16).rev().max_by_key(|key| tally[*key as usize]);
+        let key = match winner {
+            Some(key) if tally[key as usize] > 0 => key,
+            _ => continue,
+        };
+        tracing::debug!(key, votes = tally[key as usize], "chat pressed a key");
+
+        // the emulator is gone
+        if keys.send((key, KeyAction::Pressed)).is_err() {
+            return;
+        }
+        std::thread::sleep(TAP_DURATION);
+        if keys.send((key, KeyAction::Released)).is_err() {
+            return;
+        }
+    }
+}
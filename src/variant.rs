@@ -0,0 +1,136 @@
+use crate::ProgramOptions;
+
+/* address programs are loaded at */
+pub const PROGRAM_START: usize = 0x200;
+
+/* machine lineage, in order of extension: each variant runs the programs of the previous ones */
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Variant {
+    Chip8,
+    Schip,
+    XoChip, // not supported by the core, its programs run with the SCHIP behaviour at best
+}
+
+impl Variant {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "chip8" => Some(Variant::Chip8),
+            "schip" => Some(Variant::Schip),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Variant::Chip8 => "CHIP-8",
+            Variant::Schip => "SCHIP",
+            Variant::XoChip => "XO-CHIP",
+        }
+    }
+}
+
+/* the variant introducing `opcode`, if it doesn't exist on the original CHIP-8 */
+fn exclusive_to(opcode: u16) -> Option<Variant> {
+    let (x, n) = ((opcode >> 8) & 0xF, opcode & 0xF);
+
+    match (opcode >> 12, opcode & 0xFF) {
+        // scroll down, right, left, exit, low/high resolution
+        (0x0, 0xFB..=0xFF) if x == 0 => Some(Variant::Schip),
+        (0x0, 0xC1..=0xCF) if x == 0 => Some(Variant::Schip),
+        // 16x16 sprites, large font, RPL flags
+        (0xD, _) if n == 0 => Some(Variant::Schip),
+        (0xF, 0x30 | 0x75 | 0x85) => Some(Variant::Schip),
+        // scroll up, save/load register ranges, long index, planes, audio
+        (0x0, 0xD1..=0xDF) if x == 0 => Some(Variant::XoChip),
+        (0x5, _) if n == 2 || n == 3 => Some(Variant::XoChip),
+        (0xF, 0x00) if x == 0 => Some(Variant::XoChip),
+        (0xF, 0x01 | 0x3A) => Some(Variant::XoChip),
+        (0xF, 0x02) if x == 0 => Some(Variant::XoChip),
+        _ => None,
+    }
+}
+
+/* Sprites and other data are mixed with code in a ROM, so the instructions are found by following
+ * jumps, calls and skips from the entry point; computed jumps (Bnnn) can't be followed. Returns
+ * their addresses and opcodes, in the order they're found.
+ */
+pub fn reachable(program: &[u8]) -> Vec<(usize, u16)> {
+    let mut res = Vec::new();
+    let mut visited = vec![false; program.len()];
+    let mut pending = vec![PROGRAM_START];
+
+    while let Some(mut pc) = pending.pop() {
+        loop {
+            let offset = match pc.checked_sub(PROGRAM_START) {
+                Some(offset) if offset + 1 < program.len() => offset,
+                _ => break,
+            };
+            if visited[offset] {
+                break;
+            }
+            visited[offset] = true;
+
+            let opcode = u16::from_be_bytes([program[offset], program[offset + 1]]);
+            res.push((pc, opcode));
+
+            let nnn = (opcode & 0xFFF) as usize;
+            match opcode >> 12 {
+                // return, exit
+                0x0 if opcode == 0x00EE || opcode == 0x00FD => break,
+                0x1 => {
+                    pc = nnn;
+                    continue;
+                }
+                0x2 => pending.push(nnn),
+                0x3 | 0x4 | 0x5 | 0x9 | 0xE => pending.push(pc + 4),
+                0xB => break,
+                // XO-CHIP long index load, followed by a 16-bit address
+                0xF if opcode == 0xF000 => pc += 2,
+                _ => {}
+            }
+            pc += 2;
+        }
+    }
+
+    res
+}
+
+/* the most advanced variant among the reachable instructions, with the address and opcode
+ * proving it */
+pub fn detect(program: &[u8]) -> (Variant, Option<(usize, u16)>) {
+    reachable(program)
+        .into_iter()
+        .filter_map(|(pc, opcode)| Some((exclusive_to(opcode)?, Some((pc, opcode)))))
+        .fold((Variant::Chip8, None), |best, found| {
+            if found.0 > best.0 {
+                found
+            } else {
+                best
+            }
+        })
+}
+
+/* pick the behaviour of the core, unless chosen on the command line */
+pub fn resolve(options: &mut ProgramOptions) {
+    if let Some(variant) = options.variant {
+        options.schip_compatibility = variant >= Variant::Schip;
+        return;
+    }
+
+    let (variant, evidence) = detect(&options.program);
+    match evidence {
+        Some((address, opcode)) => tracing::info!(
+            "{} opcode {:04X} at {:#05X}, running as {} (override with --variant)",
+            variant.name(),
+            opcode,
+            address,
+            variant.name()
+        ),
+        None => tracing::info!("no SCHIP or XO-CHIP opcodes found, running as CHIP-8"),
+    }
+    if variant == Variant::XoChip {
+        tracing::warn!("XO-CHIP programs are not supported, running with SCHIP behaviour");
+    }
+
+    options.schip_compatibility = variant >= Variant::Schip;
+}
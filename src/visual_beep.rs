@@ -0,0 +1,61 @@
+use ggez::graphics::{self, Color, DrawParam, Quad, Rect};
+
+const BORDER_WIDTH: f32 = 8.0;
+const ICON_SIZE: f32 = 32.0;
+const MARGIN: f32 = 8.0;
+
+const HIGHLIGHT: Color = Color::new(1.0, 0.85, 0.2, 0.9);
+
+/* `--visual-beep`: the buzzer shown on screen while it sounds, muted or not, for those who can't
+ * hear it */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VisualBeep {
+    Border, // around the window
+    Icon,   // a speaker in the bottom-right corner
+}
+
+impl VisualBeep {
+    pub fn parse(arg: &str) -> Option<Self> {
+        match arg {
+            "border" => Some(Self::Border),
+            "icon" => Some(Self::Icon),
+            _ => None,
+        }
+    }
+
+    pub fn draw(self, ctx: &mut ggez::Context) -> ggez::GameResult {
+        let (width, height) = ctx.gfx.drawable_size();
+
+        let parts = match self {
+            Self::Border => vec![
+                Rect::new(0.0, 0.0, width, BORDER_WIDTH),
+                Rect::new(0.0, height - BORDER_WIDTH, width, BORDER_WIDTH),
+                Rect::new(0.0, 0.0, BORDER_WIDTH, height),
+                Rect::new(width - BORDER_WIDTH, 0.0, BORDER_WIDTH, height),
+            ],
+            Self::Icon => {
+                // body, cone and two sound waves, on an eighth of the icon grid
+                let unit = ICON_SIZE / 8.0;
+                let (x, y) = (width - ICON_SIZE - MARGIN, height - ICON_SIZE - MARGIN);
+                [(0, 3, 2, 2), (2, 1, 2, 6), (5, 2, 1, 4), (7, 0, 1, 8)]
+                    .into_iter()
+                    .map(|(left, top, w, h)| {
+                        Rect::new(
+                            x + left as f32 * unit,
+                            y + top as f32 * unit,
+                            w as f32 * unit,
+                            h as f32 * unit,
+                        )
+                    })
+                    .collect()
+            }
+        };
+
+        // on top of the frame drawn by the screen pipeline
+        let mut canvas = graphics::Canvas::from_frame(ctx, None);
+        for part in parts {
+            canvas.draw(&Quad, DrawParam::default().dest_rect(part).color(HIGHLIGHT));
+        }
+        canvas.finish(ctx)
+    }
+}
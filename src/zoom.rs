@@ -0,0 +1,87 @@
+use crate::screen::{Orientation, View};
+use ggez::graphics::{self, Color, DrawParam, Quad, Rect};
+
+/* magnifications the zoom shortcut cycles through, 1 is off */
+const LEVELS: [f32; 4] = [1.0, 2.0, 4.0, 8.0];
+
+/* emulated pixels moved by a press of an arrow key */
+const PAN_STEP: f32 = 4.0;
+
+const MAP_SCALE: f32 = 2.0; // mini-map pixels per emulated pixel
+const MARGIN: f32 = 8.0;
+
+const MAP_SCREEN: Color = Color::new(0.2, 0.2, 0.2, 0.8);
+const MAP_VIEW: Color = Color::new(1.0, 0.85, 0.2, 0.8);
+
+/* Accessibility zoom: a part of the screen magnified to fill it, moved around with the arrow keys,
+ * with a mini-map of where it is in the top-right corner of the window.
+ */
+pub struct Zoom {
+    view: View,
+    size: [f32; 2], // of the screen as shown, in emulated pixels
+}
+
+impl Zoom {
+    pub fn new(orientation: Orientation) -> Self {
+        let (width, height) = orientation.size();
+        Self {
+            view: View::whole(orientation),
+            size: [width as f32, height as f32],
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.view.level > 1.0
+    }
+
+    pub fn view(&self) -> View {
+        self.view
+    }
+
+    /* next magnification, keeping the same center */
+    pub fn cycle(&mut self) {
+        let next = LEVELS
+            .iter()
+            .position(|level| *level == self.view.level)
+            .map_or(0, |i| (i + 1) % LEVELS.len());
+        self.view.level = LEVELS[next];
+        self.pan(0.0, 0.0);
+    }
+
+    /* by arrow key presses, keeping the view within the screen */
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        for (axis, delta) in [dx, dy].into_iter().enumerate() {
+            let half = self.size[axis] / 2.0 / self.view.level;
+            self.view.center[axis] =
+                (self.view.center[axis] + delta * PAN_STEP).clamp(half, self.size[axis] - half);
+        }
+    }
+
+    pub fn draw(&self, ctx: &mut ggez::Context) -> ggez::GameResult {
+        if !self.is_active() {
+            return Ok(());
+        }
+
+        let (width, _) = ctx.gfx.drawable_size();
+        let [screen_width, screen_height] = self.size.map(|x| x * MAP_SCALE);
+        let origin = [width - screen_width - MARGIN, MARGIN];
+
+        let view_size = self.size.map(|x| x / self.view.level * MAP_SCALE);
+        let view = Rect::new(
+            origin[0] + self.view.center[0] * MAP_SCALE - view_size[0] / 2.0,
+            origin[1] + self.view.center[1] * MAP_SCALE - view_size[1] / 2.0,
+            view_size[0],
+            view_size[1],
+        );
+
+        let mut canvas = graphics::Canvas::from_frame(ctx, None);
+        canvas.draw(
+            &Quad,
+            DrawParam::default()
+                .dest_rect(Rect::new(origin[0], origin[1], screen_width, screen_height))
+                .color(MAP_SCREEN),
+        );
+        canvas.draw(&Quad, DrawParam::default().dest_rect(view).color(MAP_VIEW));
+        canvas.finish(ctx)
+    }
+}